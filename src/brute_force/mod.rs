@@ -5,14 +5,18 @@ pub(self) mod partial_path;
 pub(self) mod priorization;
 mod progress;
 
-pub use self::partial_path::{PartialPath, PathElemStorage};
+pub use self::partial_path::{
+    FixedPairBitmap, PackedFeedPair, PairBitmap, PartialPath, PathElemStorage, RuntimePairBitmap,
+};
 
 use self::{
     partial_path::StepDistance, priorization::PriorizedPartialPaths, progress::ProgressMonitor,
 };
 use crate::{
-    cache::{self, CacheModel, L1_MISS_COST, NEW_ENTRY_COST},
-    FeedIdx, MAX_FEEDS,
+    cache::{self, CacheModel, CacheStats, L1_MISS_COST, NEW_ENTRY_COST},
+    capacity::{FeedCapacity, MaxFeeds, RuntimeFeeds},
+    pair_domain::PairDomain,
+    FeedIdx,
 };
 use num_traits::identities::Zero;
 use std::{cell::RefCell, fmt::Write, time::Duration};
@@ -41,12 +45,40 @@ pub type Path = Box<[FeedPair]>;
 
 /// Use brute force to find a path which is better than our best strategy so far
 /// according to our cache simulation.
+///
+/// Path costs saturate at `cache::Cost::MAX`, so the cumulative costs of the
+/// best strategy so far must remain below that to be beaten.
+///
+/// The search's data structures are specialized for the smallest feed capacity
+/// that can hold the requested number of feeds and pairs. Beyond 32 feeds, or
+/// fewer feeds in the square domain, they are sized at run time.
+///
 pub fn search_best_path(
     num_feeds: FeedIdx,
-    entry_size: usize,
+    domain: PairDomain,
+    cache_model: &CacheModel,
     best_cumulative_cost: &mut [cache::Cost],
     iteration_timeout: Duration,
 ) -> Option<Path> {
+    assert!(
+        *best_cumulative_cost.last().unwrap() < cache::Cost::MAX,
+        "Best cumulative cost is out of brute force search range"
+    );
+
+    // Pick the search iteration's feed capacity
+    let num_pairs = domain.num_pairs(num_feeds);
+    let search_best_path_iteration = if MaxFeeds::<8>::can_hold(num_feeds, num_pairs) {
+        search_best_path_iteration::<MaxFeeds<8>>
+    } else if MaxFeeds::<16>::can_hold(num_feeds, num_pairs) {
+        search_best_path_iteration::<MaxFeeds<16>>
+    } else if MaxFeeds::<32>::can_hold(num_feeds, num_pairs) {
+        search_best_path_iteration::<MaxFeeds<32>>
+    } else if RuntimeFeeds::<u16>::can_hold(num_feeds, num_pairs) {
+        search_best_path_iteration::<RuntimeFeeds<u16>>
+    } else {
+        search_best_path_iteration::<RuntimeFeeds<u32>>
+    };
+
     // Seed simplest path record tracker
     let mut best_extra_distance = StepDistance::MAX;
     let mut best_path = None;
@@ -71,8 +103,8 @@ pub fn search_best_path(
 
             // Perform a brute force search iteration
             if let Some(path) = search_best_path_iteration(
-                num_feeds,
-                entry_size,
+                domain,
+                cache_model,
                 search_radius,
                 &mut best_cumulative_cost[..],
                 &mut best_extra_distance,
@@ -103,9 +135,7 @@ pub fn search_best_path(
 
             // Detect if we found one of the best possible paths, in which case
             // increasing the size of the search space any further is useless.
-            if *best_cumulative_cost.last().unwrap()
-                == L1_MISS_COST + cache::min_cache_cost(num_feeds)
-            {
+            if *best_cumulative_cost.last().unwrap() == min_path_cost(domain, cache_model) {
                 if BRUTE_FORCE_DEBUG_LEVEL >= 1 {
                     println!("  * We won't be able to do any better than this cache cost.");
                 }
@@ -127,9 +157,9 @@ pub fn search_best_path(
         if tolerance >= max_tolerance {
             break 'tolerance;
         } else if tolerance == 0.0 {
-            tolerance = L1_MISS_COST
+            tolerance = cache_model.l1_miss_cost()
         } else {
-            tolerance = (2 * tolerance).min(max_tolerance);
+            tolerance = tolerance.saturating_add(tolerance).min(max_tolerance);
         }
     }
 
@@ -149,6 +179,25 @@ pub fn search_best_path(
     best_path
 }
 
+/// Lower bound on the total cache cost of a path through a pair domain
+///
+/// Every feed must be loaded once. Unless one feed set of a bipartite domain
+/// fits in L1 alongside a feed of the other set, at least one feed must also
+/// be reloaded into L1 afterwards, which costs at least the cheapest reload
+/// that the cache model allows.
+///
+fn min_path_cost(domain: PairDomain, cache_model: &CacheModel) -> cache::Cost {
+    let num_feeds = cache_model.num_feeds();
+    let fits_in_l1 = |num_feeds: FeedIdx| (num_feeds as usize) < cache_model.max_l1_entries();
+    match domain {
+        PairDomain::Bipartite {
+            num_feeds_a,
+            num_feeds_b,
+        } if fits_in_l1(num_feeds_a) || fits_in_l1(num_feeds_b) => cache::min_cache_cost(num_feeds),
+        _ => cache_model.min_reload_cost() + cache::min_cache_cost(num_feeds),
+    }
+}
+
 // ---
 
 /// Iteration of best path brute force search
@@ -179,9 +228,9 @@ pub fn search_best_path(
 ///
 /// This function is the basis of that iterative behavior.
 ///
-fn search_best_path_iteration(
-    num_feeds: FeedIdx,
-    entry_size: usize,
+fn search_best_path_iteration<F: FeedCapacity>(
+    domain: PairDomain,
+    cache_model: &CacheModel,
     max_radius: FeedIdx,
     best_cumulative_cost: &mut [cache::Cost],
     best_extra_distance: &mut StepDistance,
@@ -189,44 +238,59 @@ fn search_best_path_iteration(
     timeout: Duration,
 ) -> Option<Path> {
     // Let's be reasonable here
+    let num_feeds = cache_model.num_feeds();
     let mut last_cost_record = *best_cumulative_cost.last().unwrap();
     assert!(
         num_feeds > 1
-            && num_feeds <= MAX_FEEDS
+            && num_feeds <= F::MAX_FEEDS
             && max_radius > 0
-            && entry_size > 0
-            && last_cost_record >= L1_MISS_COST + cache::min_cache_cost(num_feeds)
+            && last_cost_record >= min_path_cost(domain, cache_model)
     );
 
-    // Set up the cache model
-    let cache_model = CacheModel::new(entry_size);
+    // Check the cache model
     assert!(
         cache_model.max_l1_entries() >= 3,
         "Cache is unreasonably small"
     );
 
-    // A path should go through every point of the 2D half-square defined by
-    // x and y belonging to 0..num_feeds and y >= x. From this, we know exactly
-    // how long the best path (assuming it exists) will be.
-    let path_length = ((num_feeds as usize) * ((num_feeds as usize) + 1)) / 2;
+    // A path should go through every point of the pair domain, for example the
+    // 2D half-square defined by x and y belonging to 0..num_feeds and y >= x.
+    // From this, we know exactly how long the best path (assuming it exists)
+    // will be.
+    let path_length = domain.num_pairs(num_feeds);
     assert_eq!(best_cumulative_cost.len(), path_length);
 
     // Set up storage for paths throughout the space of feed pairs
     let path_elem_storage = RefCell::new(PathElemStorage::new());
     let mut priorized_partial_paths =
-        PriorizedPartialPaths::new(&cache_model, &path_elem_storage, path_length);
+        PriorizedPartialPaths::<F>::new(cache_model, &path_elem_storage, path_length);
 
     // We seed the path search algorithm by enumerating every possible starting
     // point for a path, under the following contraints:
     //
-    // - To match the output of other algorithms, we want y >= x.
+    // - To match the output of other algorithms, we want y >= x, or y > x if
+    //   the domain excludes autocorrelations.
     // - Starting from a point (x, y) is geometrically equivalent to starting
     //   from the symmetric point (num_points-y, num_points-x), so we don't need
     //   to explore both of these starting points to find the optimal solution.
+    //   This does not hold when the symmetric point is outside of the domain,
+    //   as in non-square bipartite domains, or when the symmetry swaps feeds
+    //   whose entries have different sizes.
+    // - In the square domain, starting from (x, y) is also equivalent to
+    //   starting from the transposed point (y, x), so y >= x remains a valid
+    //   constraint even though the domain goes through both of these points.
     //
+    let mirror_symmetric = domain.is_mirror_symmetric() && cache_model.layout().is_uniform();
     for start_y in 0..num_feeds {
-        for start_x in 0..=start_y.min(num_feeds - start_y - 1) {
-            priorized_partial_paths.create([start_x, start_y]);
+        let max_start_x = if mirror_symmetric {
+            start_y.min(num_feeds - start_y - 1)
+        } else {
+            start_y
+        };
+        for start_x in 0..=max_start_x {
+            if domain.contains(num_feeds, &[start_x, start_y]) {
+                priorized_partial_paths.create([start_x, start_y]);
+            }
         }
     }
 
@@ -239,7 +303,10 @@ fn search_best_path_iteration(
                              last_cost_record: cache::Cost|
      -> bool {
         let best_current_cost = best_cumulative_cost[curr_path_len - 1];
-        let should_prune = cost_so_far > (best_current_cost + tolerance).min(last_cost_record);
+        let should_prune = cost_so_far
+            > best_current_cost
+                .saturating_add(tolerance)
+                .min(last_cost_record);
         if should_prune && BRUTE_FORCE_DEBUG_LEVEL >= 4 {
             println!(
                 "      * That exceeds cache cost tolerance with only {}/{} steps, ignore it.",
@@ -281,14 +348,17 @@ fn search_best_path_iteration(
         // Enumerate all possible next points, the constraints on them being...
         // - Next point should be within max_radius of last point
         // - Next point should be within the iteration domain (x and y between
-        //   0 and num_feeds and y >= x).
+        //   0 and num_feeds and y >= x, or y > x without autocorrelations, or
+        //   any y in the square domain, or x in set A and y in set B in the
+        //   bipartite domain).
         // - Next point should not be any point we've previously been through
         // - The total path cache cost is not allowed to go above the best path
         //   cache cost that we've observed so far (otherwise that path is less
         //   interesting than the best path).
         let [curr_x, curr_y] = partial_path.last_step();
-        for next_x in curr_x.saturating_sub(max_radius)..(curr_x + max_radius + 1).min(num_feeds) {
-            for next_y in curr_y.saturating_sub(max_radius).max(next_x)
+        let end_x = domain.end_x(num_feeds);
+        for next_x in curr_x.saturating_sub(max_radius)..(curr_x + max_radius + 1).min(end_x) {
+            for next_y in curr_y.saturating_sub(max_radius).max(domain.min_y(next_x))
                 ..(curr_y + max_radius + 1).min(num_feeds)
             {
                 let next_step = [next_x, next_y];
@@ -353,6 +423,12 @@ fn search_best_path_iteration(
                                 partial_path.extra_distance()
                             );
                         }
+                        if BRUTE_FORCE_DEBUG_LEVEL >= 1 {
+                            println!(
+                                "    - Cache access statistics were: {}",
+                                path_stats::<F>(cache_model, &final_path)
+                            );
+                        }
                         if BRUTE_FORCE_DEBUG_LEVEL == 1 {
                             println!("    - Path was {:?}", final_path);
                         } else if BRUTE_FORCE_DEBUG_LEVEL >= 2 {
@@ -408,3 +484,21 @@ fn search_best_path_iteration(
     // Return the optimal path, if any, along with its cache cost
     best_path
 }
+
+/// Replay a path through the cache simulation in order to collect cache access
+/// statistics, which are not tracked during the search for performance reasons
+fn path_stats<F: FeedCapacity>(cache_model: &CacheModel, path: &[FeedPair]) -> CacheStats {
+    let mut cache_sim = cache_model.start_simulation::<F>();
+    cache_sim.enable_stats(cache_model);
+    for feed_pair in path.iter() {
+        // Statistics are collected even if costs overflow
+        for &feed in feed_pair.iter() {
+            let _ = cache_sim.simulate_access(cache_model, feed);
+        }
+        let _ = cache_sim.simulate_output_access(cache_model);
+    }
+    cache_sim
+        .stats()
+        .expect("Statistics were enabled above")
+        .clone()
+}
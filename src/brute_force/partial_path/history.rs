@@ -222,7 +222,10 @@ pub(super) struct PathElem {
     /// into sub-paths, and once it drops to 0, all sub-paths have been fully
     /// explored, and this path an all of its parent paths can be disposed of.
     ///
-    reference_count: u8,
+    /// A path can have as many sub-paths as there are feed pairs, which is
+    /// more than 255 with 32 feeds.
+    ///
+    reference_count: u16,
 
     /// Last step that was taken on that path
     pub curr_step: FeedPair,
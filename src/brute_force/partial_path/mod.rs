@@ -1,37 +1,30 @@
 //! Representation of a partially explored path in brute force search
 
 mod history;
+mod visited;
 
-pub use self::history::PathElemStorage;
+pub use self::{
+    history::PathElemStorage,
+    visited::{FixedPairBitmap, PairBitmap, RuntimePairBitmap},
+};
 
 use self::history::PathLink;
 use super::FeedPair;
 use crate::{
     cache::{self, CacheModel, CacheSimulation},
-    FeedIdx, MAX_FEEDS, MAX_PAIRS, _MAX_UNORDERED_PAIRS,
+    capacity::{CompactUint, FeedCapacity},
+    FeedIdx,
 };
 use num_traits::identities::Zero;
-use static_assertions::const_assert;
 use std::{
     cell::{Ref, RefCell},
     ops::Deref,
 };
 
-/// Machine word size in bits
-const WORD_SIZE: u32 = (std::mem::size_of::<usize>() * 8) as u32;
-
-/// Integer division with upper rounding
-const fn div_round_up(num: usize, denom: usize) -> usize {
-    (num / denom) + (num % denom != 0) as usize
-}
-
-/// Maximum number of machine words needed to store one bit per feed pair
-const MAX_PAIR_WORDS: usize = div_round_up(MAX_PAIRS, WORD_SIZE as usize);
-
 /// Path which we are in the process of exploring (high-level API)
-pub struct PartialPath<'storage> {
+pub struct PartialPath<'storage, F: FeedCapacity> {
     /// Inner data
-    data: PartialPathData,
+    data: PartialPathData<F>,
 
     /// Underlying cache model
     cache_model: &'storage CacheModel,
@@ -45,7 +38,7 @@ pub struct PartialPath<'storage> {
     path_elem_storage: &'storage RefCell<PathElemStorage>,
 }
 //
-impl<'storage> PartialPath<'storage> {
+impl<'storage, F: FeedCapacity> PartialPath<'storage, F> {
     /// Start a path
     pub fn new(
         path_elem_storage: &'storage RefCell<PathElemStorage>,
@@ -71,14 +64,14 @@ impl<'storage> PartialPath<'storage> {
     /// Given an extra feed pair, tell what the accumulated cache cost would
     /// become if the path was completed by this pair, and what the cache
     /// entries would then be.
-    pub fn evaluate_next_step(&self, next_step: &FeedPair) -> NextStepEvaluation {
+    pub fn evaluate_next_step(&self, next_step: &FeedPair) -> NextStepEvaluation<F> {
         self.data.evaluate_next_step(self.cache_model, next_step)
     }
 
     /// Create a new partial path which follows all the steps from this one,
     /// plus an extra step for which the new cache cost and updated cache model
     /// are provided.
-    pub fn commit_next_step(&self, next_step_eval: NextStepEvaluation) -> Self {
+    pub fn commit_next_step(&self, next_step_eval: NextStepEvaluation<F>) -> Self {
         Self {
             data: self
                 .data
@@ -90,7 +83,7 @@ impl<'storage> PartialPath<'storage> {
 
     /// Compose a partial path's data and its storage into a full PartialPath
     pub(super) fn wrap(
-        data: PartialPathData,
+        data: PartialPathData<F>,
         cache_model: &'storage CacheModel,
         path_elem_storage: &'storage RefCell<PathElemStorage>,
     ) -> Self {
@@ -112,7 +105,7 @@ impl<'storage> PartialPath<'storage> {
     }
 
     /// Get back the inner PartialPathData, typically for container insertion
-    pub(super) fn unwrap(self) -> PartialPathData {
+    pub(super) fn unwrap(self) -> PartialPathData<F> {
         // So, I'm sure there's a cleaner way to do this, but I haven't found
         // it yet. Basically, the issue here is that...
         //
@@ -131,25 +124,29 @@ impl<'storage> PartialPath<'storage> {
         //
         // 1. PartialPathData is a trivial type, almost just a bunch of numbers,
         //    it does not contain data which is unsafe to copy like &mut refs.
+        //    Its only owned resources, the boxed cache set state that is used
+        //    by non-LRU cache simulations and the heap-allocated data of
+        //    runtime-sized paths, are moved rather than copied since the
+        //    original is forgotten below.
         // 2. Double dropping will not occur because I'm forgetting `self`
         //    immediately after performing the read, with no possibility of
         //    panicking inbetween these two events.
         //
-        let data_ptr = &self.data as *const PartialPathData;
+        let data_ptr = &self.data as *const PartialPathData<F>;
         let data = unsafe { data_ptr.read() };
         std::mem::forget(self);
         data
     }
 }
 //
-impl Deref for PartialPath<'_> {
-    type Target = PartialPathData;
+impl<F: FeedCapacity> Deref for PartialPath<'_, F> {
+    type Target = PartialPathData<F>;
     fn deref(&self) -> &Self::Target {
         &self.data
     }
 }
 //
-impl Drop for PartialPath<'_> {
+impl<F: FeedCapacity> Drop for PartialPath<'_, F> {
     fn drop(&mut self) {
         self.data
             .drop_elems(&mut *self.path_elem_storage.borrow_mut())
@@ -169,10 +166,14 @@ impl Drop for PartialPath<'_> {
 /// this struct directly is not recommended, unless you are building a path
 /// container. You should instead use the `PartialPath` convenience wrapper.
 ///
+/// Its size depends on the maximal number of feeds, so it is generic over a
+/// feed capacity, which should be as small as possible. Runtime feed
+/// capacities keep the bitmap and cache clocks on the heap instead.
+///
 // SAFETY: PartialPathData must not be made to contain types which are unsafe
 //         to copy such as &mut references.
 //
-pub struct PartialPathData {
+pub struct PartialPathData<F: FeedCapacity> {
     /// Path steps and cumulative cache costs
     ///
     /// This is stored as a linked list with node deduplication across paths of
@@ -189,10 +190,10 @@ pub struct PartialPathData {
     path: PathLink,
 
     /// Length of the path in steps
-    path_len: PathLen,
+    path_len: F::PathLen,
 
     /// Last path step
-    curr_step: PackedFeedPair,
+    curr_step: F::PackedFeedPair,
 
     /// Total cache cost, accumulated over previous path steps
     curr_cost: cache::Cost,
@@ -201,67 +202,61 @@ pub struct PartialPathData {
     extra_distance: StepDistance,
 
     /// Bitmap of feed pairs that we've been through
-    visited_pairs: [usize; MAX_PAIR_WORDS],
+    visited_pairs: F::PairBitmap,
 
     /// Current state of the cache simulation
-    cache_sim: CacheSimulation,
+    cache_sim: CacheSimulation<F>,
 }
 //
-/// Type appropriate for representing path lengths
-pub type PathLen = u8;
-const_assert!(PathLen::MAX as usize >= _MAX_UNORDERED_PAIRS);
-//
-/// Type appropriate for representing feed pairs
-pub type PackedFeedPair = u8;
-const PACKED_FEED_BITS: u32 = 8 * std::mem::size_of::<PackedFeedPair>() as u32 / 2;
-const_assert!(1 << PACKED_FEED_BITS >= MAX_FEEDS);
-//
-/// Total distance that was "walked" across a set of path steps
-pub type StepDistance = u16;
-//
-impl PartialPathData {
-    /// Pack a FeedPair into a PackedFeedPair
-    //
-    // NOTE: This operation is relatively hot and must be quite fast
-    //
-    const fn pack_feed_pair(&[x, y]: &FeedPair) -> PackedFeedPair {
-        // TODO: Enable once const fn can panic
-        // debug_assert!(x < MAX_FEEDS && y < MAX_FEEDS);
-        (x << PACKED_FEED_BITS) + y
-    }
+/// Integer type into which a feed pair can be packed
+pub trait PackedFeedPair: Copy {
+    /// Number of bits used by each feed of the pair
+    const FEED_BITS: u32;
 
-    /// Unpack a PackedFeedPair into a FeedPair
+    /// Pack a FeedPair
     //
-    // NOTE: This operation is super hot and must be very fast
+    // NOTE: This operation is relatively hot and must be quite fast
     //
-    const fn unpack_feed_pair(packed: PackedFeedPair) -> FeedPair {
-        let y = packed & ((1 << PACKED_FEED_BITS) - 1);
-        let x = packed >> PACKED_FEED_BITS;
-        [x, y]
-    }
+    fn pack(pair: &FeedPair) -> Self;
 
-    /// Index of a certain coordinate in the visited_pairs bitvec
+    /// Unpack into a FeedPair
     //
     // NOTE: This operation is super hot and must be very fast
     //
-    const fn coord_to_bit_index(&[x, y]: &FeedPair) -> (usize, u32) {
-        let linear_index = y as usize * MAX_FEEDS as usize + x as usize;
-        let word_index = linear_index / WORD_SIZE as usize;
-        let bit_index = (linear_index % WORD_SIZE as usize) as u32;
-        (word_index, bit_index)
-    }
-
-    // Inverse of to_bit_index
-    //
-    // NOTE: This operation is very rare and can be slow
-    //
-    const fn bit_index_to_coord(word: usize, bit: u32) -> FeedPair {
-        let linear_index = word * WORD_SIZE as usize + bit as usize;
-        let y = (linear_index / (MAX_FEEDS as usize)) as FeedIdx;
-        let x = (linear_index % (MAX_FEEDS as usize)) as FeedIdx;
-        [x, y]
-    }
-
+    fn unpack(self) -> FeedPair;
+}
+//
+macro_rules! impl_packed_feed_pair {
+    ($($packed:ty),*) => {
+        $(
+            impl PackedFeedPair for $packed {
+                const FEED_BITS: u32 = 8 * std::mem::size_of::<$packed>() as u32 / 2;
+
+                #[inline(always)]
+                fn pack(&[x, y]: &FeedPair) -> Self {
+                    debug_assert!(
+                        (x as Self) < (1 << Self::FEED_BITS) && (y as Self) < (1 << Self::FEED_BITS)
+                    );
+                    ((x as Self) << Self::FEED_BITS) + y as Self
+                }
+
+                #[inline(always)]
+                fn unpack(self) -> FeedPair {
+                    let y = self & ((1 << Self::FEED_BITS) - 1);
+                    let x = self >> Self::FEED_BITS;
+                    [x as FeedIdx, y as FeedIdx]
+                }
+            }
+        )*
+    };
+}
+//
+impl_packed_feed_pair!(u8, u16, u32);
+//
+/// Total distance that was "walked" across a set of path steps
+pub type StepDistance = u16;
+//
+impl<F: FeedCapacity> PartialPathData<F> {
     /// Start a path
     //
     // NOTE: This operation is very rare and can be slow
@@ -274,26 +269,23 @@ impl PartialPathData {
         let mut cache_sim = cache_model.start_simulation();
         let mut curr_cost = cache::Cost::zero();
         for &feed in start_step.iter() {
-            curr_cost += cache_sim.simulate_access(&cache_model, feed);
+            curr_cost = curr_cost.saturating_add(saturated_cost(
+                cache_sim.simulate_access(&cache_model, feed),
+            ));
         }
+        curr_cost = curr_cost.saturating_add(saturated_cost(
+            cache_sim.simulate_output_access(cache_model),
+        ));
 
         let path = PathLink::new(path_elem_storage, start_step, curr_cost, None);
 
-        let mut visited_pairs = [0; MAX_PAIR_WORDS];
-        for word in 0..MAX_PAIR_WORDS {
-            let mut current_word = 0;
-            for bit in (0..WORD_SIZE).rev() {
-                let [x, y] = Self::bit_index_to_coord(word, bit);
-                let visited = [x, y] == start_step;
-                current_word = (current_word << 1) | (visited as usize);
-            }
-            visited_pairs[word] = current_word;
-        }
+        let mut visited_pairs = F::PairBitmap::new(cache_model.num_feeds());
+        visited_pairs.insert(&start_step);
 
         Self {
             path,
-            path_len: 1,
-            curr_step: Self::pack_feed_pair(&start_step),
+            path_len: F::PathLen::from_usize(1),
+            curr_step: F::PackedFeedPair::pack(&start_step),
             curr_cost,
             visited_pairs,
             cache_sim,
@@ -306,7 +298,7 @@ impl PartialPathData {
     // NOTE: This operation is hot and must be fast
     //
     pub fn len(&self) -> usize {
-        self.path_len as usize
+        self.path_len.to_usize()
     }
 
     /// Tell how much excess distance was covered through path stepping
@@ -322,7 +314,7 @@ impl PartialPathData {
     // NOTE: This operation is hot and must be fast
     //
     pub fn last_step(&self) -> FeedPair {
-        Self::unpack_feed_pair(self.curr_step)
+        self.curr_step.unpack()
     }
 
     /// Iterate over the path steps and cumulative costs in reverse step order
@@ -355,9 +347,7 @@ impl PartialPathData {
     // NOTE: This operation is super hot and must be very fast
     //
     pub fn contains(&self, pair: &FeedPair) -> bool {
-        let (word, bit) = Self::coord_to_bit_index(pair);
-        debug_assert!(word < self.visited_pairs.len());
-        (unsafe { self.visited_pairs.get_unchecked(word) } & (1 << bit)) != 0
+        self.visited_pairs.contains(pair)
     }
 
     /// Get the accumulated cache cost of following this path so far
@@ -371,6 +361,9 @@ impl PartialPathData {
     /// Given an extra feed pair, tell what the accumulated cache cost would
     /// become if the path was completed by this pair, and what the cache
     /// entries would then be.
+    ///
+    /// The accumulated cache cost saturates at `Cost::MAX` instead of
+    /// overflowing, so that such paths are pruned by the search.
     //
     // NOTE: This operation is super hot and must be very fast
     //
@@ -378,13 +371,17 @@ impl PartialPathData {
         &self,
         cache_model: &CacheModel,
         &next_step: &FeedPair,
-    ) -> NextStepEvaluation {
+    ) -> NextStepEvaluation<F> {
         let mut next_cache = self.cache_sim.clone();
-        let next_cost = self.cost_so_far()
-            + next_step
-                .iter()
-                .map(|&feed| next_cache.simulate_access(&cache_model, feed))
-                .sum::<cache::Cost>();
+        let mut next_cost = self.cost_so_far();
+        for &feed in next_step.iter() {
+            next_cost = next_cost.saturating_add(saturated_cost(
+                next_cache.simulate_access(&cache_model, feed),
+            ));
+        }
+        next_cost = next_cost.saturating_add(saturated_cost(
+            next_cache.simulate_output_access(cache_model),
+        ));
         NextStepEvaluation {
             next_step,
             next_cost,
@@ -401,7 +398,7 @@ impl PartialPathData {
     fn commit_next_step(
         &self,
         path_elem_storage: &mut PathElemStorage,
-        next_step_eval: NextStepEvaluation,
+        next_step_eval: NextStepEvaluation<F>,
     ) -> Self {
         let NextStepEvaluation {
             next_step,
@@ -413,10 +410,7 @@ impl PartialPathData {
         let next_path = PathLink::new(path_elem_storage, next_step, next_cost, prev_steps);
 
         let mut next_visited_pairs = self.visited_pairs.clone();
-        let (word, bit) = Self::coord_to_bit_index(&next_step);
-        // TODO: Make sure the bound check is elided or has negligible cost, if
-        //       it is too expensive use get_unchecked.
-        next_visited_pairs[word] |= 1 << bit;
+        next_visited_pairs.insert(&next_step);
 
         let step_length: StepDistance = self
             .last_step()
@@ -427,10 +421,12 @@ impl PartialPathData {
             })
             .sum();
 
+        let mut path_len = self.path_len;
+        path_len.increment();
         Self {
             path: next_path,
-            path_len: self.path_len + 1,
-            curr_step: Self::pack_feed_pair(&next_step),
+            path_len,
+            curr_step: F::PackedFeedPair::pack(&next_step),
             curr_cost: next_cost,
             visited_pairs: next_visited_pairs,
             cache_sim: next_cache,
@@ -449,7 +445,7 @@ impl PartialPathData {
 }
 
 /// Result of `PartialPath::evaluate_next_step()`
-pub struct NextStepEvaluation {
+pub struct NextStepEvaluation<F: FeedCapacity> {
     /// Next step to be taken (repeated to simplify commit_next_step signature)
     next_step: FeedPair,
 
@@ -457,5 +453,12 @@ pub struct NextStepEvaluation {
     pub next_cost: cache::Cost,
 
     /// Cache state after taking this step
-    next_cache: CacheSimulation,
+    next_cache: CacheSimulation<F>,
+}
+
+/// Total cost of a cache access, saturated to the maximal cache cost if it
+/// overflows, which rules out the path that it belongs to
+fn saturated_cost(cost: Result<cache::AccessCost, cache::CostOverflow>) -> cache::Cost {
+    cost.and_then(|cost| cost.total())
+        .unwrap_or(cache::Cost::MAX)
 }
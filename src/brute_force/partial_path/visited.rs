@@ -0,0 +1,125 @@
+//! Representation of the set of feed pairs that a PartialPath went through
+
+use super::FeedPair;
+use crate::{
+    capacity::{div_round_up, WORD_SIZE},
+    FeedIdx,
+};
+
+/// Bitmap with one bit per feed pair, stored as rows of `stride()` bits
+///
+/// The stride is at least the number of feeds, and a compile-time constant
+/// when possible since computing bit indices is super hot.
+///
+pub trait PairBitmap: Clone {
+    /// Set up an empty bitmap for a certain number of feeds
+    fn new(num_feeds: FeedIdx) -> Self;
+
+    /// Number of bits per row of the bitmap
+    fn stride(&self) -> usize;
+
+    /// Machine words of the bitmap
+    fn words(&self) -> &[usize];
+
+    /// Mutable access to the machine words of the bitmap
+    fn words_mut(&mut self) -> &mut [usize];
+
+    /// Index of a certain coordinate in the bitmap
+    //
+    // NOTE: This operation is super hot and must be very fast
+    //
+    #[inline(always)]
+    fn coord_to_bit_index(&self, &[x, y]: &FeedPair) -> (usize, u32) {
+        let linear_index = y as usize * self.stride() + x as usize;
+        let word_index = linear_index / WORD_SIZE as usize;
+        let bit_index = (linear_index % WORD_SIZE as usize) as u32;
+        (word_index, bit_index)
+    }
+
+    /// Tell whether the bitmap contains a certain feed pair
+    //
+    // NOTE: This operation is super hot and must be very fast
+    //
+    #[inline(always)]
+    fn contains(&self, pair: &FeedPair) -> bool {
+        let (word, bit) = self.coord_to_bit_index(pair);
+        let words = self.words();
+        debug_assert!(word < words.len());
+        (unsafe { words.get_unchecked(word) } & (1 << bit)) != 0
+    }
+
+    /// Add a feed pair to the bitmap
+    //
+    // NOTE: This operation is relatively hot and must be quite fast
+    //
+    #[inline(always)]
+    fn insert(&mut self, pair: &FeedPair) {
+        let (word, bit) = self.coord_to_bit_index(pair);
+        // TODO: Make sure the bound check is elided or has negligible cost, if
+        //       it is too expensive use get_unchecked.
+        self.words_mut()[word] |= 1 << bit;
+    }
+}
+
+/// Bitmap whose stride is the maximal number of feeds, stored inline
+#[derive(Clone, Copy, Debug)]
+pub struct FixedPairBitmap<const MAX_FEEDS: FeedIdx, const WORDS: usize>([usize; WORDS]);
+//
+impl<const MAX_FEEDS: FeedIdx, const WORDS: usize> PairBitmap
+    for FixedPairBitmap<MAX_FEEDS, WORDS>
+{
+    fn new(num_feeds: FeedIdx) -> Self {
+        debug_assert!(num_feeds <= MAX_FEEDS);
+        Self([0; WORDS])
+    }
+
+    #[inline(always)]
+    fn stride(&self) -> usize {
+        MAX_FEEDS as usize
+    }
+
+    #[inline(always)]
+    fn words(&self) -> &[usize] {
+        &self.0[..]
+    }
+
+    #[inline(always)]
+    fn words_mut(&mut self) -> &mut [usize] {
+        &mut self.0[..]
+    }
+}
+
+/// Bitmap whose stride is the actual number of feeds, stored on the heap
+#[derive(Clone, Debug)]
+pub struct RuntimePairBitmap {
+    /// Number of feeds
+    num_feeds: FeedIdx,
+
+    /// Machine words of the bitmap
+    words: Box<[usize]>,
+}
+//
+impl PairBitmap for RuntimePairBitmap {
+    fn new(num_feeds: FeedIdx) -> Self {
+        let num_pairs = num_feeds as usize * num_feeds as usize;
+        Self {
+            num_feeds,
+            words: vec![0; div_round_up(num_pairs, WORD_SIZE as usize)].into_boxed_slice(),
+        }
+    }
+
+    #[inline(always)]
+    fn stride(&self) -> usize {
+        self.num_feeds as usize
+    }
+
+    #[inline(always)]
+    fn words(&self) -> &[usize] {
+        &self.words[..]
+    }
+
+    #[inline(always)]
+    fn words_mut(&mut self) -> &mut [usize] {
+        &mut self.words[..]
+    }
+}
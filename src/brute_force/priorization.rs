@@ -17,7 +17,7 @@
 //! priorizing the most promising tracks over others.
 
 use super::{partial_path::PartialPathData, FeedPair, PartialPath, PathElemStorage};
-use crate::cache::CacheModel;
+use crate::{cache::CacheModel, capacity::FeedCapacity};
 use rand::prelude::*;
 use std::{cell::RefCell, cmp::Ordering, collections::BinaryHeap};
 
@@ -37,7 +37,7 @@ use std::{cell::RefCell, cmp::Ordering, collections::BinaryHeap};
 const MAX_STORED_PATHS: usize = 100_000;
 
 /// PartialPath container that enables priorization and randomization
-pub struct PriorizedPartialPaths<'storage> {
+pub struct PriorizedPartialPaths<'storage, F: FeedCapacity> {
     /// Underlying cache model
     cache_model: &'storage CacheModel,
 
@@ -49,7 +49,7 @@ pub struct PriorizedPartialPaths<'storage> {
     /// - The slot at index i contains paths of length self.min_path_len + i
     /// - The first slot at index 0 is guaranteed to contain some paths.
     //
-    paths_by_len: Vec<BinaryHeap<PriorizedPath>>,
+    paths_by_len: Vec<BinaryHeap<PriorizedPath<F>>>,
 
     /// Minimal path length that hasn't been fully explored
     min_path_len: usize,
@@ -64,7 +64,7 @@ pub struct PriorizedPartialPaths<'storage> {
     iters_since_last_rng: usize,
 }
 //
-impl<'storage> PriorizedPartialPaths<'storage> {
+impl<'storage, F: FeedCapacity> PriorizedPartialPaths<'storage, F> {
     /// Create the collection
     pub fn new(
         cache_model: &'storage CacheModel,
@@ -98,7 +98,7 @@ impl<'storage> PriorizedPartialPaths<'storage> {
 
     /// Record a pre-existing partial path
     #[inline(always)]
-    pub fn push(&mut self, path: PartialPath) {
+    pub fn push(&mut self, path: PartialPath<F>) {
         debug_assert!(
             // Initial seeding
             (self.min_path_len == 1 && self.curr_path_len == 0 && path.len() == 1)
@@ -111,7 +111,7 @@ impl<'storage> PriorizedPartialPaths<'storage> {
 
     /// Extract one of the highest-priority paths
     #[inline(always)]
-    pub fn pop(&mut self, mut rng: impl Rng) -> Option<PartialPath<'storage>> {
+    pub fn pop(&mut self, mut rng: impl Rng) -> Option<PartialPath<'storage, F>> {
         // Handle edge case where all paths have already been processed
         if self.paths_by_len.is_empty() {
             return None;
@@ -184,7 +184,7 @@ impl<'storage> PriorizedPartialPaths<'storage> {
     /// This is very expensive, but only meant to be done when a new cache cost
     /// record is achieved, which doesn't happen very often.
     ///
-    pub fn prune(&mut self, mut should_prune: impl FnMut(&PartialPath) -> bool) {
+    pub fn prune(&mut self, mut should_prune: impl FnMut(&PartialPath<F>) -> bool) {
         let mut new_paths = BinaryHeap::with_capacity(self.high_water_mark);
         for old_paths in &mut self.paths_by_len {
             for priorized_path in old_paths.drain() {
@@ -213,7 +213,7 @@ impl<'storage> PriorizedPartialPaths<'storage> {
     }
 }
 //
-impl Drop for PriorizedPartialPaths<'_> {
+impl<F: FeedCapacity> Drop for PriorizedPartialPaths<'_, F> {
     fn drop(&mut self) {
         for paths in self.paths_by_len.drain(..) {
             for priorized_path in paths {
@@ -225,14 +225,14 @@ impl Drop for PriorizedPartialPaths<'_> {
     }
 }
 
-struct PriorizedPath(PartialPathData);
+struct PriorizedPath<F: FeedCapacity>(PartialPathData<F>);
 //
 /// Priorize cache cost, then given equal cache cost priorize simplest path
 type Priority = (isize, isize);
 //
-impl PriorizedPath {
+impl<F: FeedCapacity> PriorizedPath<F> {
     /// Build a PriorizedPath from a PartialPath
-    fn new(path: PartialPath) -> Self {
+    fn new(path: PartialPath<F>) -> Self {
         Self(path.unwrap())
     }
 
@@ -241,7 +241,7 @@ impl PriorizedPath {
         self,
         cache_model: &'storage CacheModel,
         path_elem_storage: &'storage RefCell<PathElemStorage>,
-    ) -> PartialPath<'storage> {
+    ) -> PartialPath<'storage, F> {
         PartialPath::wrap(self.0, cache_model, path_elem_storage)
     }
 
@@ -255,21 +255,21 @@ impl PriorizedPath {
     }
 }
 //
-impl PartialEq for PriorizedPath {
+impl<F: FeedCapacity> PartialEq for PriorizedPath<F> {
     fn eq(&self, other: &Self) -> bool {
         self.priority().eq(&other.priority())
     }
 }
 //
-impl PartialOrd for PriorizedPath {
+impl<F: FeedCapacity> PartialOrd for PriorizedPath<F> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         self.priority().partial_cmp(&other.priority())
     }
 }
 //
-impl Eq for PriorizedPath {}
+impl<F: FeedCapacity> Eq for PriorizedPath<F> {}
 //
-impl Ord for PriorizedPath {
+impl<F: FeedCapacity> Ord for PriorizedPath<F> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.partial_cmp(&other).unwrap()
     }
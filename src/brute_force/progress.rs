@@ -1,6 +1,7 @@
 //! Brute force search progress monitoring and reporting
 
 use super::{priorization::PriorizedPartialPaths, BRUTE_FORCE_DEBUG_LEVEL};
+use crate::capacity::FeedCapacity;
 use std::time::{Duration, Instant};
 
 /// Mechanism to track and report brute force search progress
@@ -44,7 +45,7 @@ impl ProgressMonitor {
     }
 
     /// Record that a path step has been taken, print periodical reports
-    pub fn record_step(&mut self, paths: &PriorizedPartialPaths) {
+    pub fn record_step<F: FeedCapacity>(&mut self, paths: &PriorizedPartialPaths<F>) {
         // Only report on progress infrequently so that search isn't slowed down
         self.total_path_steps += 1;
         const CLOCK_CHECK_RATE: u64 = 1 << 17;
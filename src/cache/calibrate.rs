@@ -0,0 +1,306 @@
+//! Calibration of the cache model on the host CPU
+
+use super::{
+    hierarchy::HierarchyError, latency_curve::LatencyCurveError, sysfs, CacheHierarchy, CacheLevel,
+    Cost, LatencyCurve,
+};
+use rand::seq::SliceRandom;
+use std::{
+    fmt::{self, Display},
+    time::Instant,
+};
+
+/// Size of the smallest working set that is measured, in bytes
+const MIN_WORKING_SET: usize = 4 * 1024;
+
+/// Number of measured working sets per doubling of the working set size
+const STEPS_PER_OCTAVE: i32 = 4;
+
+/// Cache line size that is assumed by the pointer-chasing benchmark
+///
+/// If the actual cache line size is smaller, this will only waste some memory.
+/// If it is larger, the benchmark will get spurious cache hits.
+///
+const LINE_SIZE: usize = 128;
+
+/// Number of pointers that are chased per timed run
+const ACCESSES_PER_RUN: usize = 1 << 20;
+
+/// Number of timed runs per working set, of which the fastest is kept
+const RUNS_PER_WORKING_SET: usize = 3;
+
+/// Relative latency increase beyond which a measurement is not considered to
+/// belong to the current latency plateau anymore
+const PLATEAU_TOLERANCE: f32 = 0.15;
+
+/// Minimal number of measurements in a latency plateau
+const MIN_PLATEAU_LEN: usize = 3;
+
+/// Minimal latency ratio between the plateaus of successive cache levels
+///
+/// Latency often creeps up slowly before reaching a cache level's capacity,
+/// which can look like several plateaus. These are merged back together.
+///
+const MIN_LEVEL_LATENCY_RATIO: f32 = 1.5;
+
+/// Measure the latency of random memory accesses for growing working sets,
+/// from a few KiB to `max_working_set` bytes, in nanoseconds
+///
+/// This uses a pointer-chasing microbenchmark, where each access depends on
+/// the previous one, so that the CPU cannot overlap their latencies. Accesses
+/// follow a random cyclic permutation of the working set's cache lines in
+/// order to defeat hardware prefetchers.
+///
+/// `progress` is called after each measurement with the working set size and
+/// the measured latency, as this takes a while. Measurements that do not form
+/// a valid latency curve, for example because `max_working_set` is too small
+/// for latency to rise, are reported as an error.
+///
+pub fn measure_latency_curve(
+    max_working_set: usize,
+    mut progress: impl FnMut(usize, f32),
+) -> Result<LatencyCurve, CalibrationError> {
+    let mut rng = rand::thread_rng();
+    let mut points = Vec::new();
+    for step in 0.. {
+        let working_set =
+            (MIN_WORKING_SET as f64 * 2.0f64.powf(step as f64 / STEPS_PER_OCTAVE as f64)) as usize
+                / LINE_SIZE
+                * LINE_SIZE;
+        if working_set > max_working_set {
+            break;
+        }
+        if points.last().map(|&(bytes, _)| bytes) == Some(working_set) {
+            continue;
+        }
+
+        // Link the cache lines of the working set in a random cycle
+        const STRIDE: usize = LINE_SIZE / std::mem::size_of::<usize>();
+        let num_lines = working_set / LINE_SIZE;
+        let mut order = (0..num_lines).collect::<Vec<_>>();
+        order.shuffle(&mut rng);
+        let mut buffer = vec![0; num_lines * STRIDE];
+        for (idx, &line) in order.iter().enumerate() {
+            buffer[line * STRIDE] = order[(idx + 1) % num_lines] * STRIDE;
+        }
+
+        // Warm up the caches, then time the fastest of a few runs
+        let mut position = 0;
+        for _ in 0..num_lines {
+            position = buffer[position];
+        }
+        let mut best_latency = f32::INFINITY;
+        for _ in 0..RUNS_PER_WORKING_SET {
+            let start = Instant::now();
+            for _ in 0..ACCESSES_PER_RUN {
+                position = buffer[position];
+            }
+            let latency = start.elapsed().as_secs_f32() * 1e9 / ACCESSES_PER_RUN as f32;
+            best_latency = best_latency.min(latency);
+        }
+        assert!(position < buffer.len());
+
+        progress(working_set, best_latency);
+        points.push((working_set, best_latency));
+    }
+    LatencyCurve::new(points).map_err(CalibrationError::LatencyCurve)
+}
+
+/// Deduce a cache hierarchy from a measured latency curve
+///
+/// The latency curve is expected to exhibit a plateau for every cache level,
+/// followed by a final plateau (or rise) for main memory. The miss cost of
+/// each level is then deduced from the latency of the next plateau, in units
+/// of L1 cache miss latency.
+///
+/// If sysfs reports as many cache levels as we found, their capacity, geometry
+/// and line transfer costs are taken from there, as they are more accurate.
+/// Otherwise, capacities are estimated from the end of each latency plateau.
+///
+pub fn detect_hierarchy(curve: &LatencyCurve) -> Result<CacheHierarchy, CalibrationError> {
+    let (capacities, miss_costs) = detect_levels(curve)?;
+    let num_levels = capacities.len();
+
+    // Use sysfs' more precise description of the cache levels if it agrees
+    if let Ok(hierarchy) = CacheHierarchy::from_sysfs("/sys") {
+        if hierarchy.levels.len() == num_levels {
+            let name = format!("Calibrated {}", hierarchy.name);
+            let mut hierarchy = hierarchy
+                .with_miss_costs(&miss_costs)
+                .map_err(CalibrationError::Hierarchy)?;
+            hierarchy.name = name;
+            return Ok(hierarchy);
+        }
+    }
+    let levels = capacities
+        .into_iter()
+        .zip(miss_costs)
+        .enumerate()
+        .map(|(idx, (capacity, miss_cost))| CacheLevel {
+            capacity,
+            miss_cost,
+            line_transfer_cost: sysfs::default_costs(idx, num_levels).1,
+            associativity: None,
+        })
+        .collect();
+    CacheHierarchy::new("Calibrated host", levels).map_err(CalibrationError::Hierarchy)
+}
+
+/// Deduce the capacity and miss cost of each cache level from a measured
+/// latency curve, see `detect_hierarchy()`
+fn detect_levels(curve: &LatencyCurve) -> Result<(Vec<usize>, Vec<Cost>), CalibrationError> {
+    // Split the curve into plateaus of roughly constant latency
+    let mut plateaus: Vec<Vec<(usize, f32)>> = Vec::new();
+    let mut current: Vec<(usize, f32)> = Vec::new();
+    for &(bytes, latency) in curve.points() {
+        match current.first() {
+            Some(&(_, base_latency)) if latency <= base_latency * (1.0 + PLATEAU_TOLERANCE) => {}
+            _ => {
+                if current.len() >= MIN_PLATEAU_LEN {
+                    plateaus.push(current);
+                }
+                current = Vec::new();
+            }
+        }
+        current.push((bytes, latency));
+    }
+    if current.len() >= MIN_PLATEAU_LEN || !plateaus.is_empty() {
+        // If memory latency is still rising, its last measurement will do
+        plateaus.push(current);
+    }
+
+    // Merge plateaus whose latencies are too close to be distinct cache levels
+    let plateau_latency = |plateau: &[(usize, f32)]| {
+        plateau.iter().map(|&(_, latency)| latency).sum::<f32>() / plateau.len() as f32
+    };
+    let mut merged_plateaus: Vec<Vec<(usize, f32)>> = Vec::with_capacity(plateaus.len());
+    for plateau in plateaus {
+        match merged_plateaus.last_mut() {
+            Some(last)
+                if plateau_latency(&plateau) < plateau_latency(last) * MIN_LEVEL_LATENCY_RATIO =>
+            {
+                last.extend(plateau)
+            }
+            _ => merged_plateaus.push(plateau),
+        }
+    }
+    let plateaus = merged_plateaus;
+    if plateaus.len() < 2 {
+        return Err(CalibrationError::TooFewPlateaus {
+            num_plateaus: plateaus.len(),
+        });
+    }
+
+    // Translate plateaus into cache level capacities and miss costs, in units
+    // of L1 cache miss latency
+    let hit_latency = plateau_latency(&plateaus[0]);
+    let l1_miss_latency = plateau_latency(&plateaus[1]) - hit_latency;
+    let num_levels = plateaus.len() - 1;
+    let capacities = plateaus[..num_levels]
+        .iter()
+        .map(|plateau| plateau.last().unwrap().0)
+        .collect::<Vec<_>>();
+    let miss_costs = plateaus[1..]
+        .iter()
+        .enumerate()
+        .map(|(level, plateau)| {
+            let miss_cost = (plateau_latency(plateau) - hit_latency) / l1_miss_latency;
+            Cost::checked_from_num(miss_cost)
+                .ok_or(CalibrationError::MissCostOutOfRange { level, miss_cost })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((capacities, miss_costs))
+}
+
+/// Error while calibrating the cache model
+#[derive(Debug)]
+pub enum CalibrationError {
+    /// The measured latencies do not form a valid latency curve
+    LatencyCurve(LatencyCurveError),
+
+    /// The latency curve does not exhibit a plateau for at least one cache
+    /// level and main memory
+    TooFewPlateaus { num_plateaus: usize },
+
+    /// The miss cost of a cache level, in units of L1 miss latency, is out of
+    /// Cost range
+    MissCostOutOfRange { level: usize, miss_cost: f32 },
+
+    /// The detected cache hierarchy is not valid
+    Hierarchy(HierarchyError),
+}
+//
+impl Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LatencyCurve(error) => write!(f, "invalid latency measurements: {}", error),
+            Self::TooFewPlateaus { num_plateaus } => write!(
+                f,
+                "expected latency plateaus for at least one cache level and main memory, found {}",
+                num_plateaus
+            ),
+            Self::MissCostOutOfRange { level, miss_cost } => write!(
+                f,
+                "L{} miss cost of {} L1 miss latencies is out of range",
+                level + 1,
+                miss_cost
+            ),
+            Self::Hierarchy(error) => write!(f, "detected cache hierarchy is invalid: {}", error),
+        }
+    }
+}
+//
+impl std::error::Error for CalibrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::LatencyCurve(error) => Some(error),
+            Self::Hierarchy(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a latency curve with one sample per power of two working set
+    /// size from 4 KiB on, given (last working set size, latency) plateaus
+    fn plateau_curve(plateaus: &[(usize, f32)]) -> LatencyCurve {
+        let mut points = Vec::new();
+        let mut bytes = MIN_WORKING_SET;
+        for &(end_bytes, latency) in plateaus {
+            while bytes <= end_bytes {
+                points.push((bytes, latency));
+                bytes *= 2;
+            }
+        }
+        LatencyCurve::new(points).unwrap()
+    }
+
+    #[test]
+    fn detects_cache_levels() {
+        let curve = plateau_curve(&[(32 * 1024, 1.0), (512 * 1024, 4.0), (1 << 30, 31.0)]);
+        let (capacities, miss_costs) = detect_levels(&curve).unwrap();
+        assert_eq!(capacities, vec![32 * 1024, 512 * 1024]);
+        assert_eq!(miss_costs, vec![Cost::from_num(1), Cost::from_num(10)]);
+    }
+
+    #[test]
+    fn rejects_unusable_curves() {
+        // A single cache level, with no sign of main memory
+        let curve =
+            LatencyCurve::new(vec![(4096, 1.0), (8192, 1.0), (16384, 1.0), (32768, 1.01)]).unwrap();
+        assert!(matches!(
+            detect_levels(&curve),
+            Err(CalibrationError::TooFewPlateaus { num_plateaus: 1 })
+        ));
+
+        // A main memory that is too slow for our Cost type
+        let curve = plateau_curve(&[(32 * 1024, 1.0), (512 * 1024, 2.0), (1 << 30, 20000.0)]);
+        assert!(matches!(
+            detect_levels(&curve),
+            Err(CalibrationError::MissCostOutOfRange { level: 1, .. })
+        ));
+    }
+}
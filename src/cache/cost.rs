@@ -0,0 +1,156 @@
+//! Accumulation of cache costs over iteration paths
+
+use super::Cost;
+use fixed::{types::extra::U2, FixedU32};
+use std::{
+    fmt::{self, Debug, Display},
+    ops::Sub,
+};
+
+/// Fixed-point cost type with the same precision as `Cost`, but enough range
+/// to accumulate the cache cost of very long iteration paths
+pub type WideCost = FixedU32<U2>;
+
+/// Numeric type into which per-access cache costs can be accumulated
+///
+/// The cache simulation computes the cost of each access as a `Cost`, which is
+/// kept as small as possible so that the partial paths of the brute force
+/// search stay compact. Summing these costs over a whole iteration path can
+/// exceed the range of this type, however, so evaluations which do not need to
+/// be compact can accumulate them into a wider type instead.
+///
+/// All accumulations are checked, so that overflow is reported as a
+/// `CostOverflow` error instead of wrapping around or aborting the program.
+///
+pub trait CostAccumulator: Copy + Debug + Display + PartialOrd + Sub<Output = Self> {
+    /// Human-readable name of this type, for error messages
+    const NAME: &'static str;
+
+    /// Zero cost, which accumulation starts from
+    const ZERO: Self;
+
+    /// Convert a per-access cost into this type, which must be lossless
+    fn from_cost(cost: Cost) -> Self;
+
+    /// Add two costs, or return None if the result is out of range
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+
+    /// Convert this cost back into a `Cost`, if it is within its range
+    fn to_cost(self) -> Option<Cost>;
+
+    /// Convert this cost to floating point, for statistics
+    fn to_f32(self) -> f32;
+
+    /// Add a per-access cost to this accumulator
+    fn add_cost(&mut self, cost: Cost) -> Result<(), CostOverflow> {
+        self.add_assign_checked(Self::from_cost(cost))
+    }
+
+    /// Add another accumulated cost to this accumulator
+    fn add_assign_checked(&mut self, rhs: Self) -> Result<(), CostOverflow> {
+        *self = self
+            .checked_add(rhs)
+            .ok_or_else(CostOverflow::new::<Self>)?;
+        Ok(())
+    }
+
+    /// Sum a set of accumulated costs
+    fn checked_sum(costs: impl IntoIterator<Item = Self>) -> Result<Self, CostOverflow> {
+        let mut sum = Self::ZERO;
+        for cost in costs {
+            sum.add_assign_checked(cost)?;
+        }
+        Ok(sum)
+    }
+}
+//
+impl CostAccumulator for Cost {
+    const NAME: &'static str = "16-bit fixed-point";
+    const ZERO: Self = Cost::from_bits(0);
+
+    fn from_cost(cost: Cost) -> Self {
+        cost
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        Cost::checked_add(self, rhs)
+    }
+
+    fn to_cost(self) -> Option<Cost> {
+        Some(self)
+    }
+
+    fn to_f32(self) -> f32 {
+        self.to_num()
+    }
+}
+//
+impl CostAccumulator for WideCost {
+    const NAME: &'static str = "32-bit fixed-point";
+    const ZERO: Self = WideCost::from_bits(0);
+
+    fn from_cost(cost: Cost) -> Self {
+        WideCost::from_bits(cost.to_bits().into())
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        WideCost::checked_add(self, rhs)
+    }
+
+    fn to_cost(self) -> Option<Cost> {
+        Cost::checked_from_num(self)
+    }
+
+    fn to_f32(self) -> f32 {
+        self.to_num()
+    }
+}
+//
+impl CostAccumulator for f64 {
+    const NAME: &'static str = "floating-point";
+    const ZERO: Self = 0.0;
+
+    fn from_cost(cost: Cost) -> Self {
+        cost.to_num()
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        Some(self + rhs).filter(|sum| sum.is_finite())
+    }
+
+    fn to_cost(self) -> Option<Cost> {
+        Cost::checked_from_num(self)
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+/// A cache cost went out of the range of the type it was accumulated into
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CostOverflow {
+    /// Name of the accumulator type
+    accumulator: &'static str,
+}
+//
+impl CostOverflow {
+    /// Report an overflow of a certain accumulator type
+    pub(crate) fn new<C: CostAccumulator>() -> Self {
+        Self {
+            accumulator: C::NAME,
+        }
+    }
+}
+//
+impl Display for CostOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cache cost overflowed its {} accumulator",
+            self.accumulator
+        )
+    }
+}
+//
+impl std::error::Error for CostOverflow {}
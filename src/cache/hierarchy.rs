@@ -0,0 +1,1099 @@
+//! Description of CPU cache hierarchies: named presets and configuration files
+
+use super::{Cost, COST_GRANULARITY, L1_MISS_COST};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Description of one level of the CPU cache hierarchy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CacheLevel {
+    /// Capacity of this cache level in bytes
+    pub capacity: usize,
+
+    /// Latency of an access that misses this cache level, but hits the next
+    /// one (or main memory if this is the last level), in units of L1 misses
+    pub miss_cost: Cost,
+
+    /// Extra cost of bringing each cache line of an entry from the next level
+    /// into this one after a miss, in the same units as `miss_cost`
+    ///
+    /// This is usually a small fraction of the miss latency, so we need more
+    /// precision than what our Cost type provides.
+    ///
+    pub line_transfer_cost: f32,
+
+    /// Geometry of this cache level, if known, for set-associative simulations
+    pub associativity: Option<SetAssociativity>,
+}
+//
+impl CacheLevel {
+    /// Cost of transferring the cache lines of an entry of a certain size from
+    /// the next cache level into this one, in the same units as `miss_cost`
+    pub(crate) fn transfer_cost(&self, entry_size: usize) -> f32 {
+        let line_size = self
+            .associativity
+            .map_or(DEFAULT_LINE_SIZE, |associativity| associativity.line_size);
+        entry_size.div_ceil(line_size) as f32 * self.line_transfer_cost
+    }
+}
+
+/// Cache line size that is assumed for cache levels of unknown geometry
+const DEFAULT_LINE_SIZE: usize = 64;
+
+/// Geometry of a set-associative cache level
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SetAssociativity {
+    /// Number of cache lines in each cache set
+    pub ways: usize,
+
+    /// Number of cache sets
+    pub sets: usize,
+
+    /// Size of a cache line in bytes
+    pub line_size: usize,
+}
+//
+impl SetAssociativity {
+    /// Total capacity of the cache level in bytes
+    pub const fn capacity(&self) -> usize {
+        self.ways * self.sets * self.line_size
+    }
+}
+
+/// Shorthand for describing cache levels in presets
+///
+/// Miss costs are given in quarters of an L1 miss, which is the granularity of
+/// our Cost type.
+///
+const fn level(
+    capacity: usize,
+    quarter_misses: u16,
+    line_transfer_cost: f32,
+    geometry: [usize; 3],
+) -> CacheLevel {
+    let [ways, sets, line_size] = geometry;
+    CacheLevel {
+        capacity,
+        miss_cost: Cost::from_bits(quarter_misses * COST_GRANULARITY / 4),
+        line_transfer_cost,
+        associativity: Some(SetAssociativity {
+            ways,
+            sets,
+            line_size,
+        }),
+    }
+}
+
+/// Zen3 cache hierarchy
+///
+/// Numbers stolen from the latency plot of Anandtech's Zen3 review, not very
+/// precise but we only care about the orders of magnitude on recent CPUs...
+///
+/// We're using numbers from the region where most of AnandTech's tests move out
+/// of cache. The "full random" test is probably too pessimistic here.
+///
+/// We're taking the height of cache latencies plateaux as our cost figure and
+/// the abscissa of half-plateau as our capacity figure. The L3 figures are
+/// those of a single CCX, which is what a single thread gets to use.
+///
+/// Per-line transfer costs are rough estimates derived from the single-core
+/// bandwidth of each level.
+///
+/// All costs are expressed relative to the latency of L1 cache misses, and the
+/// other presets follow the same conventions.
+///
+pub const ZEN3_CACHE_LEVELS: [CacheLevel; 3] = [
+    CacheLevel {
+        capacity: 32 * 1024,
+        miss_cost: L1_MISS_COST,
+        line_transfer_cost: 0.15,
+        associativity: Some(SetAssociativity {
+            ways: 8,
+            sets: 64,
+            line_size: 64,
+        }),
+    },
+    level(512 * 1024, 5 * 4, 0.25, [8, 1024, 64]),
+    level(32 * 1024 * 1024, 30 * 4, 1.0, [16, 32 * 1024, 64]),
+];
+
+/// Zen4 cache hierarchy (one CCD), with ~14 cycles L2, ~50 cycles L3 and
+/// ~75ns DRAM latency
+pub const ZEN4_CACHE_LEVELS: [CacheLevel; 3] = [
+    level(32 * 1024, 4, 0.15, [8, 64, 64]),
+    level(1024 * 1024, 14, 0.25, [8, 2048, 64]),
+    level(32 * 1024 * 1024, 27 * 4, 1.0, [16, 32 * 1024, 64]),
+];
+
+/// Skylake-SP cache hierarchy (28-core die, non-inclusive mesh L3), with ~14
+/// cycles L2, ~77 cycles L3 and ~90ns DRAM latency
+pub const SKYLAKE_SP_CACHE_LEVELS: [CacheLevel; 3] = [
+    level(32 * 1024, 4, 0.15, [8, 64, 64]),
+    level(1024 * 1024, 22, 0.45, [16, 1024, 64]),
+    level(28 * 1408 * 1024, 18 * 4, 1.4, [11, 28 * 2048, 64]),
+];
+
+/// Ice Lake client cache hierarchy (4-core die), with ~13 cycles L2, ~42
+/// cycles L3 and ~100ns LPDDR4X latency
+pub const ICE_LAKE_CACHE_LEVELS: [CacheLevel; 3] = [
+    level(48 * 1024, 4, 0.15, [12, 64, 64]),
+    level(512 * 1024, 13, 0.25, [8, 1024, 64]),
+    level(8 * 1024 * 1024, 30 * 4, 1.2, [16, 8 * 1024, 64]),
+];
+
+/// Neoverse N1 cache hierarchy (Graviton2-like, with a 32 MiB system level
+/// cache), with ~11 cycles L2, ~30ns SLC and ~120ns DRAM latency
+pub const NEOVERSE_N1_CACHE_LEVELS: [CacheLevel; 3] = [
+    level(64 * 1024, 4, 0.2, [4, 256, 64]),
+    level(1024 * 1024, 7 * 4, 0.45, [8, 2048, 64]),
+    level(32 * 1024 * 1024, 27 * 4, 1.8, [16, 32 * 1024, 64]),
+];
+
+/// Apple M1 performance core cache hierarchy, with ~18 cycles L2 and ~100ns
+/// DRAM latency. The system level cache is smaller than the L2 cache, so it
+/// does not make much of a difference for a single thread and is left out.
+pub const APPLE_M1_CACHE_LEVELS: [CacheLevel; 2] = [
+    level(128 * 1024, 4, 0.25, [8, 128, 128]),
+    level(12 * 1024 * 1024, 17 * 4, 0.4, [12, 8 * 1024, 128]),
+];
+
+/// Named cache hierarchy presets
+const PRESETS: [(&str, &[CacheLevel]); 6] = [
+    ("Zen3", &ZEN3_CACHE_LEVELS),
+    ("Zen4", &ZEN4_CACHE_LEVELS),
+    ("Skylake-SP", &SKYLAKE_SP_CACHE_LEVELS),
+    ("Ice-Lake", &ICE_LAKE_CACHE_LEVELS),
+    ("Neoverse-N1", &NEOVERSE_N1_CACHE_LEVELS),
+    ("Apple-M1", &APPLE_M1_CACHE_LEVELS),
+];
+
+/// Relationship between the contents of successive cache levels
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Inclusion {
+    /// Every level holds a copy of the contents of the levels above it, and
+    /// entries are inserted into every level when they are accessed
+    ///
+    /// The effective capacity of the hierarchy is that of its largest level.
+    ///
+    Inclusive,
+
+    /// Every level except the first one is a victim cache, which is only
+    /// filled by entries that are evicted from the level above it. Hitting an
+    /// entry in one of these levels moves it back into the first level.
+    ///
+    /// The effective capacity of the hierarchy is the sum of level capacities.
+    ///
+    Exclusive,
+
+    /// Non-inclusive non-exclusive (NINE) hierarchy, where misses insert
+    /// entries into every level that missed, but each level only sees the
+    /// accesses that missed the levels above it and evicts entries without
+    /// notifying the other levels
+    ///
+    /// The effective capacity of the hierarchy is somewhere between the
+    /// capacity of its largest level and the sum of level capacities.
+    ///
+    NonInclusive,
+}
+//
+impl Inclusion {
+    /// Name of this hierarchy mode, for reporting purposes
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Inclusive => "inclusive",
+            Self::Exclusive => "exclusive",
+            Self::NonInclusive => "NINE",
+        }
+    }
+
+    /// Look up a hierarchy mode by name (case-insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Inclusive, Self::Exclusive, Self::NonInclusive]
+            .iter()
+            .copied()
+            .find(|inclusion| inclusion.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Range of effective capacities (in bytes) that a cache hierarchy has in
+    /// this mode
+    pub fn effective_capacity(self, hierarchy: &CacheHierarchy) -> (usize, usize) {
+        let largest = hierarchy
+            .levels
+            .iter()
+            .map(|level| level.capacity)
+            .max()
+            .unwrap_or(0);
+        let sum = hierarchy.levels.iter().map(|level| level.capacity).sum();
+        match self {
+            Self::Inclusive => (largest, largest),
+            Self::Exclusive => (sum, sum),
+            Self::NonInclusive => (largest, sum),
+        }
+    }
+}
+
+/// Description of a CPU cache hierarchy
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheHierarchy {
+    /// Name of the cache hierarchy, for reporting purposes
+    pub name: String,
+
+    /// Cache levels, from the closest to the CPU to the farthest from it
+    pub levels: Vec<CacheLevel>,
+}
+//
+impl CacheHierarchy {
+    /// Build a cache hierarchy from a list of cache levels, checking that it
+    /// makes sense
+    pub fn new(name: impl Into<String>, levels: Vec<CacheLevel>) -> Result<Self, HierarchyError> {
+        let result = Self {
+            name: name.into(),
+            levels,
+        };
+        result.validate()?;
+        Ok(result)
+    }
+
+    /// Load one of the named cache hierarchy presets (case-insensitive)
+    pub fn from_preset(name: &str) -> Result<Self, HierarchyError> {
+        let (name, levels) = PRESETS
+            .iter()
+            .find(|(preset_name, _levels)| preset_name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| HierarchyError::UnknownPreset(name.to_owned()))?;
+        Self::new(*name, levels.to_vec())
+    }
+
+    /// Names of the available cache hierarchy presets
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _levels)| *name)
+    }
+
+    /// Load a cache hierarchy from a configuration file
+    ///
+    /// Files with a `.json` extension are parsed as JSON, everything else is
+    /// parsed as TOML. Only the subset of these formats that is needed to
+    /// describe a cache hierarchy is supported: an optional name, followed by
+    /// a list of cache levels, from the closest to the CPU to the farthest
+    /// from it. In TOML, this looks like this:
+    ///
+    /// ```toml
+    /// name = "My CPU"
+    ///
+    /// [[level]]
+    /// capacity = 32768  # In bytes
+    /// miss_cost = 1     # In units of L1 cache misses
+    /// line_transfer_cost = 0.15  # Optional, defaults to zero
+    /// ways = 8          # Optional geometry (ways, sets and line_size), which
+    /// sets = 64         # is used by set-associative simulations
+    /// line_size = 64
+    /// ```
+    ///
+    /// ...and the JSON equivalent is:
+    ///
+    /// ```json
+    /// {
+    ///     "name": "My CPU",
+    ///     "level": [
+    ///         { "capacity": 32768, "miss_cost": 1, "line_transfer_cost": 0.15,
+    ///           "ways": 8, "sets": 64, "line_size": 64 }
+    ///     ]
+    /// }
+    /// ```
+    ///
+    /// If no name is specified, the path to the file is used as a name.
+    ///
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, HierarchyError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(HierarchyError::Io)?;
+        let default_name = path.display().to_string();
+        if path.extension() == Some("json".as_ref()) {
+            Self::parse_json(&source, default_name)
+        } else {
+            Self::parse_toml(&source, default_name)
+        }
+    }
+
+    /// Write this cache hierarchy in the TOML format of `from_file()`
+    pub fn to_toml(&self) -> String {
+        let mut toml = format!("name = \"{}\"\n", self.name.replace(&['"', '\\'][..], "'"));
+        for level in self.levels.iter() {
+            toml.push_str(&format!(
+                "\n[[level]]\ncapacity = {}\nmiss_cost = {}\nline_transfer_cost = {}\n",
+                level.capacity, level.miss_cost, level.line_transfer_cost
+            ));
+            if let Some(associativity) = &level.associativity {
+                toml.push_str(&format!(
+                    "ways = {}\nsets = {}\nline_size = {}\n",
+                    associativity.ways, associativity.sets, associativity.line_size
+                ));
+            }
+        }
+        toml
+    }
+
+    /// Parse the contents of a TOML configuration file, see `from_file()`
+    fn parse_toml(source: &str, default_name: String) -> Result<Self, HierarchyError> {
+        let mut name = default_name;
+        let mut levels = Vec::new();
+        let mut current_level: Option<LevelFields> = None;
+        for (line_idx, line) in source.lines().enumerate() {
+            let syntax_error = |message: String| HierarchyError::Syntax {
+                line: line_idx + 1,
+                message,
+            };
+
+            // Strip comments and whitespace, skip blank lines
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            // Start a new cache level on every [[level]] header
+            if line.starts_with('[') {
+                if line != "[[level]]" {
+                    return Err(syntax_error(format!("unexpected table header {}", line)));
+                }
+                if let Some(fields) = current_level.take() {
+                    levels.push(fields.build(levels.len())?);
+                }
+                current_level = Some(LevelFields::default());
+                continue;
+            }
+
+            // Otherwise, we expect a key = value pair
+            let (key, value) = if let Some(equal_pos) = line.find('=') {
+                (line[..equal_pos].trim(), line[equal_pos + 1..].trim())
+            } else {
+                return Err(syntax_error(format!("expected key = value, got {}", line)));
+            };
+            let value = Value::parse(value, Format::Toml).map_err(syntax_error)?;
+            match (&mut current_level, key) {
+                (None, "name") => name = value.into_string().map_err(syntax_error)?,
+                (None, _) => return Err(syntax_error(format!("unexpected top-level key {}", key))),
+                (Some(fields), key) => fields.set(key, value).map_err(syntax_error)?,
+            }
+        }
+        if let Some(fields) = current_level.take() {
+            levels.push(fields.build(levels.len())?);
+        }
+        Self::new(name, levels)
+    }
+
+    /// Parse the contents of a JSON configuration file, see `from_file()`
+    fn parse_json(source: &str, default_name: String) -> Result<Self, HierarchyError> {
+        let mut parser = JsonParser { source, pos: 0 };
+        let (line, root) = parser.parse_document()?;
+        let syntax_error = |line, message: String| HierarchyError::Syntax { line, message };
+        let root = if let Json::Object(members) = root {
+            members
+        } else {
+            return Err(syntax_error(line, "expected a JSON object".to_owned()));
+        };
+
+        let mut name = default_name;
+        let mut levels = Vec::new();
+        for (line, key, value) in root {
+            match (key.as_str(), value) {
+                ("name", Json::Scalar(value)) => {
+                    name = value.into_string().map_err(|e| syntax_error(line, e))?
+                }
+                ("level", Json::Array(json_levels)) => {
+                    for (line, json_level) in json_levels {
+                        let members = if let Json::Object(members) = json_level {
+                            members
+                        } else {
+                            return Err(syntax_error(line, "expected a cache level".to_owned()));
+                        };
+                        let mut fields = LevelFields::default();
+                        for (line, key, value) in members {
+                            if let Json::Scalar(value) = value {
+                                fields.set(&key, value).map_err(|e| syntax_error(line, e))?;
+                            } else {
+                                return Err(syntax_error(
+                                    line,
+                                    format!("unexpected {} value", key),
+                                ));
+                            }
+                        }
+                        levels.push(fields.build(levels.len())?);
+                    }
+                }
+                (key, _) => {
+                    return Err(syntax_error(
+                        line,
+                        format!("unexpected top-level key {}", key),
+                    ))
+                }
+            }
+        }
+        Self::new(name, levels)
+    }
+
+    /// Check that the cache hierarchy makes sense
+    ///
+    /// There should be at least one cache level, capacities and miss costs
+    /// should be nonzero and grow as we move away from the CPU, and the
+    /// geometry of set-associative levels should match their capacity.
+    ///
+    pub fn validate(&self) -> Result<(), HierarchyError> {
+        if self.levels.is_empty() {
+            return Err(HierarchyError::NoLevels);
+        }
+        let mut prev_level: Option<&CacheLevel> = None;
+        for (idx, level) in self.levels.iter().enumerate() {
+            if level.capacity == 0 {
+                return Err(HierarchyError::ZeroCapacity { level: idx });
+            }
+            if level.miss_cost == 0.0 {
+                return Err(HierarchyError::ZeroMissCost { level: idx });
+            }
+            if !(level.line_transfer_cost.is_finite() && level.line_transfer_cost >= 0.0) {
+                return Err(HierarchyError::BadTransferCost { level: idx });
+            }
+            if let Some(prev_level) = prev_level {
+                if level.capacity <= prev_level.capacity {
+                    return Err(HierarchyError::ShrinkingCapacity { level: idx });
+                }
+                if level.miss_cost < prev_level.miss_cost {
+                    return Err(HierarchyError::DecreasingMissCost { level: idx });
+                }
+            }
+            if let Some(associativity) = level.associativity {
+                if associativity.ways == 0
+                    || associativity.sets == 0
+                    || associativity.line_size == 0
+                    || associativity.capacity() != level.capacity
+                {
+                    return Err(HierarchyError::BadGeometry { level: idx });
+                }
+            }
+            prev_level = Some(level);
+        }
+        Ok(())
+    }
+}
+//
+impl Display for CacheHierarchy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} cache hierarchy", self.name)?;
+        for (idx, level) in self.levels.iter().enumerate() {
+            write!(
+                f,
+                "\n- L{}: {}, miss cost {}",
+                idx + 1,
+                format_size(level.capacity),
+                level.miss_cost
+            )?;
+            if level.line_transfer_cost > 0.0 {
+                write!(f, " + {} per line", level.line_transfer_cost)?;
+            }
+            if let Some(associativity) = level.associativity {
+                write!(
+                    f,
+                    ", {}-way with {} sets of {} lines",
+                    associativity.ways,
+                    associativity.sets,
+                    format_size(associativity.line_size)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error while setting up a cache hierarchy
+#[derive(Debug)]
+pub enum HierarchyError {
+    /// There is no cache level
+    NoLevels,
+
+    /// A cache level has zero capacity
+    ZeroCapacity { level: usize },
+
+    /// A cache level is not larger than the previous one
+    ShrinkingCapacity { level: usize },
+
+    /// A cache level has zero miss cost
+    ZeroMissCost { level: usize },
+
+    /// A cache level has a lower miss cost than the previous one
+    DecreasingMissCost { level: usize },
+
+    /// A cache level has a negative or non-finite line transfer cost
+    BadTransferCost { level: usize },
+
+    /// A cache level's geometry is degenerate or does not match its capacity
+    BadGeometry { level: usize },
+
+    /// There is no cache hierarchy preset with that name
+    UnknownPreset(String),
+
+    /// The configuration file could not be read
+    Io(io::Error),
+
+    /// The configuration file is not correctly formatted
+    Syntax { line: usize, message: String },
+
+    /// A cache level from the configuration file is missing a field
+    MissingField { level: usize, field: &'static str },
+
+    /// The host's cache hierarchy could not be read from sysfs
+    Sysfs { path: PathBuf, message: String },
+
+    /// The number of miss costs does not match the number of cache levels
+    MissCostCount { expected: usize, actual: usize },
+}
+//
+impl Display for HierarchyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoLevels => write!(f, "there should be at least one cache level"),
+            Self::ZeroCapacity { level } => write!(f, "L{} capacity should not be zero", level + 1),
+            Self::ShrinkingCapacity { level } => write!(
+                f,
+                "L{} capacity should be larger than L{} capacity",
+                level + 1,
+                level
+            ),
+            Self::ZeroMissCost { level } => {
+                write!(f, "L{} miss cost should not be zero", level + 1)
+            }
+            Self::DecreasingMissCost { level } => write!(
+                f,
+                "L{} miss cost should not be lower than L{} miss cost",
+                level + 1,
+                level
+            ),
+            Self::BadTransferCost { level } => write!(
+                f,
+                "L{} line transfer cost should be finite and nonnegative",
+                level + 1
+            ),
+            Self::BadGeometry { level } => write!(
+                f,
+                "L{} geometry should be nonzero and match its capacity",
+                level + 1
+            ),
+            Self::UnknownPreset(name) => write!(
+                f,
+                "unknown cache hierarchy preset {:?} (available presets: {})",
+                name,
+                CacheHierarchy::preset_names()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Io(error) => write!(f, "failed to read cache hierarchy file: {}", error),
+            Self::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            Self::MissingField { level, field } => {
+                write!(f, "L{} is missing field {}", level + 1, field)
+            }
+            Self::Sysfs { path, message } => write!(f, "{}: {}", path.display(), message),
+            Self::MissCostCount { expected, actual } => write!(
+                f,
+                "expected {} miss costs (one per cache level), got {}",
+                expected, actual
+            ),
+        }
+    }
+}
+//
+impl std::error::Error for HierarchyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Fields of a `[[level]]` table that were specified so far
+#[derive(Default)]
+struct LevelFields {
+    capacity: Option<usize>,
+    miss_cost: Option<Cost>,
+    line_transfer_cost: Option<f32>,
+    ways: Option<usize>,
+    sets: Option<usize>,
+    line_size: Option<usize>,
+}
+//
+impl LevelFields {
+    /// Set one of the fields
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        let field = match key {
+            "capacity" => &mut self.capacity,
+            "ways" => &mut self.ways,
+            "sets" => &mut self.sets,
+            "line_size" => &mut self.line_size,
+            "miss_cost" => {
+                let miss_cost = value.into_f64()?;
+                self.miss_cost = Some(
+                    Cost::checked_from_num(miss_cost)
+                        .ok_or_else(|| format!("miss cost {} is out of range", miss_cost))?,
+                );
+                return Ok(());
+            }
+            "line_transfer_cost" => {
+                self.line_transfer_cost = Some(value.into_f64()? as f32);
+                return Ok(());
+            }
+            _ => return Err(format!("unexpected cache level key {}", key)),
+        };
+        *field = Some(value.into_usize()?);
+        Ok(())
+    }
+
+    /// Turn the fields into a cache level description
+    fn build(self, level: usize) -> Result<CacheLevel, HierarchyError> {
+        let missing_field = |field| HierarchyError::MissingField { level, field };
+        let associativity = match (self.ways, self.sets, self.line_size) {
+            (None, None, None) => None,
+            (ways, sets, line_size) => Some(SetAssociativity {
+                ways: ways.ok_or_else(|| missing_field("ways"))?,
+                sets: sets.ok_or_else(|| missing_field("sets"))?,
+                line_size: line_size.ok_or_else(|| missing_field("line_size"))?,
+            }),
+        };
+        Ok(CacheLevel {
+            capacity: self.capacity.ok_or_else(|| missing_field("capacity"))?,
+            miss_cost: self.miss_cost.ok_or_else(|| missing_field("miss_cost"))?,
+            line_transfer_cost: self.line_transfer_cost.unwrap_or(0.0),
+            associativity,
+        })
+    }
+}
+
+/// Configuration file format, which affects the accepted number syntax
+#[derive(Clone, Copy)]
+enum Format {
+    /// TOML, where digits may be separated by underscores (as in 32_768)
+    Toml,
+
+    /// JSON, where numbers follow the strict JSON grammar
+    Json,
+}
+
+/// Value from a configuration file
+enum Value {
+    Integer(u64),
+    Float(f64),
+    String(String),
+}
+//
+impl Value {
+    /// Parse a value (with comments and whitespace already stripped)
+    fn parse(text: &str, format: Format) -> Result<Self, String> {
+        if let Some(quoted) = text.strip_prefix('"') {
+            return match quoted.strip_suffix('"') {
+                Some(string) if !string.contains('"') && !string.contains('\\') => {
+                    Ok(Self::String(string.to_owned()))
+                }
+                _ => Err(format!("unsupported string {}", text)),
+            };
+        }
+        let number = match format {
+            Format::Toml => strip_digit_separators(text),
+            Format::Json => Some(text.to_owned()).filter(|_| is_json_number(text)),
+        }
+        .ok_or_else(|| format!("unsupported value {}", text))?;
+        if let Ok(integer) = number.parse() {
+            Ok(Self::Integer(integer))
+        } else if let Ok(float) = number.parse() {
+            Ok(Self::Float(float))
+        } else {
+            Err(format!("unsupported value {}", text))
+        }
+    }
+
+    /// Interpret this value as a string
+    fn into_string(self) -> Result<String, String> {
+        match self {
+            Self::String(string) => Ok(string),
+            _ => Err("expected a string".to_owned()),
+        }
+    }
+
+    /// Interpret this value as a nonnegative integer
+    fn into_usize(self) -> Result<usize, String> {
+        match self {
+            Self::Integer(integer) => {
+                usize::try_from(integer).map_err(|_| format!("integer {} is too large", integer))
+            }
+            _ => Err("expected a nonnegative integer".to_owned()),
+        }
+    }
+
+    /// Interpret this value as a floating-point number
+    fn into_f64(self) -> Result<f64, String> {
+        match self {
+            Self::Integer(integer) => Ok(integer as f64),
+            Self::Float(float) => Ok(float),
+            Self::String(_) => Err("expected a number".to_owned()),
+        }
+    }
+}
+
+/// Remove the underscores that TOML allows between the digits of a number,
+/// failing if an underscore is not surrounded by digits
+fn strip_digit_separators(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    for (idx, _) in text.match_indices('_') {
+        let is_digit = |idx: Option<usize>| matches!(idx.and_then(|idx| bytes.get(idx)), Some(byte) if byte.is_ascii_digit());
+        if !is_digit(idx.checked_sub(1)) || !is_digit(Some(idx + 1)) {
+            return None;
+        }
+    }
+    Some(text.replace('_', ""))
+}
+
+/// Check that a string follows the JSON number grammar, which is stricter than
+/// that of Rust's number parsers (no leading +, leading zeros, inf, nan...)
+fn is_json_number(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let mut pos = 0;
+    let skip_digits = |pos: &mut usize| {
+        let start = *pos;
+        while matches!(bytes.get(*pos), Some(byte) if byte.is_ascii_digit()) {
+            *pos += 1;
+        }
+        *pos - start
+    };
+    if bytes.get(pos) == Some(&b'-') {
+        pos += 1;
+    }
+    let int_start = pos;
+    match skip_digits(&mut pos) {
+        0 => return false,
+        1 => {}
+        _ if bytes[int_start] == b'0' => return false,
+        _ => {}
+    }
+    if bytes.get(pos) == Some(&b'.') {
+        pos += 1;
+        if skip_digits(&mut pos) == 0 {
+            return false;
+        }
+    }
+    if let Some(b'e') | Some(b'E') = bytes.get(pos) {
+        pos += 1;
+        if let Some(b'+') | Some(b'-') = bytes.get(pos) {
+            pos += 1;
+        }
+        if skip_digits(&mut pos) == 0 {
+            return false;
+        }
+    }
+    pos == bytes.len()
+}
+
+/// JSON value, with the line numbers of array elements and object members
+enum Json {
+    Scalar(Value),
+    Array(Vec<(usize, Json)>),
+    Object(Vec<(usize, String, Json)>),
+}
+
+/// Minimal JSON parser, which supports the subset of JSON that is needed to
+/// describe a cache hierarchy (no null, booleans or string escapes)
+struct JsonParser<'source> {
+    source: &'source str,
+    pos: usize,
+}
+//
+impl JsonParser<'_> {
+    /// Parse a whole JSON document, telling on which line it starts
+    fn parse_document(&mut self) -> Result<(usize, Json), HierarchyError> {
+        self.skip_whitespace();
+        let line = self.line();
+        let value = self.parse_value()?;
+        self.skip_whitespace();
+        if self.pos < self.source.len() {
+            return Err(self.error("unexpected data after JSON document"));
+        }
+        Ok((line, value))
+    }
+
+    /// Parse a JSON value, starting at a non-whitespace character
+    fn parse_value(&mut self) -> Result<Json, HierarchyError> {
+        match self.peek() {
+            Some('{') => {
+                let mut members = Vec::new();
+                self.parse_sequence('}', |parser| {
+                    let line = parser.line();
+                    let key = if let Json::Scalar(Value::String(key)) = parser.parse_value()? {
+                        key
+                    } else {
+                        return Err(parser.error("expected a string key"));
+                    };
+                    parser.skip_whitespace();
+                    parser.expect(':')?;
+                    parser.skip_whitespace();
+                    members.push((line, key, parser.parse_value()?));
+                    Ok(())
+                })?;
+                Ok(Json::Object(members))
+            }
+            Some('[') => {
+                let mut elements = Vec::new();
+                self.parse_sequence(']', |parser| {
+                    elements.push((parser.line(), parser.parse_value()?));
+                    Ok(())
+                })?;
+                Ok(Json::Array(elements))
+            }
+            Some('"') => {
+                let rest = &self.source[self.pos + 1..];
+                let len = rest
+                    .find('"')
+                    .ok_or_else(|| self.error("unterminated string"))?;
+                let text = &self.source[self.pos..self.pos + len + 2];
+                self.pos += len + 2;
+                Value::parse(text, Format::Json)
+                    .map(Json::Scalar)
+                    .map_err(|e| self.error(&e))
+            }
+            Some(_) => {
+                let rest = &self.source[self.pos..];
+                let len = rest
+                    .find(|c: char| c == ',' || c == '}' || c == ']' || c.is_whitespace())
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                Value::parse(&rest[..len], Format::Json)
+                    .map(Json::Scalar)
+                    .map_err(|e| self.error(&e))
+            }
+            None => Err(self.error("unexpected end of JSON document")),
+        }
+    }
+
+    /// Parse the comma-separated items of an array or object, starting at the
+    /// opening delimiter and stopping after the closing one
+    fn parse_sequence(
+        &mut self,
+        closing: char,
+        mut parse_item: impl FnMut(&mut Self) -> Result<(), HierarchyError>,
+    ) -> Result<(), HierarchyError> {
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(closing) {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            parse_item(self)?;
+            self.skip_whitespace();
+            if self.peek() == Some(closing) {
+                self.pos += 1;
+                return Ok(());
+            }
+            self.expect(',')?;
+            self.skip_whitespace();
+        }
+    }
+
+    /// Consume an expected character
+    fn expect(&mut self, expected: char) -> Result<(), HierarchyError> {
+        if self.peek() == Some(expected) {
+            self.pos += expected.len_utf8();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {:?}", expected)))
+        }
+    }
+
+    /// Look at the next character
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    /// Skip whitespace
+    fn skip_whitespace(&mut self) {
+        let rest = &self.source[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Line number of the current position
+    fn line(&self) -> usize {
+        self.source[..self.pos].matches('\n').count() + 1
+    }
+
+    /// Report a syntax error at the current position
+    fn error(&self, message: &str) -> HierarchyError {
+        HierarchyError::Syntax {
+            line: self.line(),
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// Strip the comment at the end of a configuration file line, if any
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (pos, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..pos],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Display a size in bytes using binary prefixes
+pub(crate) fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024 && size & 1023 == 0 && unit < UNITS.len() - 1 {
+        size /= 1024;
+        unit += 1;
+    }
+    format!("{} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_round_trip_through_toml() {
+        for name in CacheHierarchy::preset_names() {
+            let preset = CacheHierarchy::from_preset(name).unwrap();
+            let parsed = CacheHierarchy::parse_toml(&preset.to_toml(), String::new()).unwrap();
+            assert_eq!(parsed, preset);
+        }
+    }
+
+    #[test]
+    fn toml_accepts_documented_syntax() {
+        let hierarchy = CacheHierarchy::parse_toml(
+            r#"
+            # Leading comment
+            name = "My CPU"
+
+            [[level]]
+            capacity = 32_768  # In bytes
+            miss_cost = 1
+            line_transfer_cost = 0.15
+            ways = 8
+            sets = 64
+            line_size = 64
+
+            [[level]]
+            capacity = 1_048_576
+            miss_cost = 12.5
+            "#,
+            "default".to_owned(),
+        )
+        .unwrap();
+        assert_eq!(hierarchy.name, "My CPU");
+        assert_eq!(hierarchy.levels.len(), 2);
+        assert_eq!(hierarchy.levels[0].capacity, 32768);
+        assert_eq!(
+            hierarchy.levels[0].associativity,
+            Some(SetAssociativity {
+                ways: 8,
+                sets: 64,
+                line_size: 64
+            })
+        );
+        assert_eq!(hierarchy.levels[1].capacity, 1024 * 1024);
+        assert_eq!(hierarchy.levels[1].miss_cost, Cost::from_num(12.5));
+        assert_eq!(hierarchy.levels[1].line_transfer_cost, 0.0);
+        assert_eq!(hierarchy.levels[1].associativity, None);
+    }
+
+    #[test]
+    fn toml_rejects_malformed_input() {
+        let level = "[[level]]\ncapacity = 32768\nmiss_cost = 1\n";
+        for source in &[
+            "[level]\ncapacity = 32768\nmiss_cost = 1\n".to_owned(),
+            format!("{}capacity_bytes = 1\n", level),
+            format!("{}ways = 8\n", level),
+            format!("{}sets = 64_\n", level),
+            format!("{}sets = _64\n", level),
+            format!("{}sets = 6__4\n", level),
+            format!("{}sets = \"64\"\n", level),
+            "capacity = 32768\n".to_owned(),
+            "[[level]]\nmiss_cost = 1\n".to_owned(),
+            "[[level]]\ncapacity 32768\n".to_owned(),
+            "name = \"No levels\"\n".to_owned(),
+        ] {
+            assert!(
+                CacheHierarchy::parse_toml(source, String::new()).is_err(),
+                "Accepted invalid TOML:\n{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn json_accepts_documented_syntax() {
+        let hierarchy = CacheHierarchy::parse_json(
+            r#"{
+                "level": [
+                    { "capacity": 32768, "miss_cost": 1, "line_transfer_cost": 1.5e-1,
+                      "ways": 8, "sets": 64, "line_size": 64 },
+                    { "capacity": 524288, "miss_cost": 5 }
+                ]
+            }"#,
+            "default".to_owned(),
+        )
+        .unwrap();
+        assert_eq!(hierarchy.name, "default");
+        assert_eq!(hierarchy.levels.len(), 2);
+        assert_eq!(hierarchy.levels[0].line_transfer_cost, 0.15);
+        assert_eq!(hierarchy.levels[1].capacity, 512 * 1024);
+        assert_eq!(hierarchy.levels[1].miss_cost, Cost::from_num(5));
+    }
+
+    #[test]
+    fn json_rejects_malformed_input() {
+        for number in &[
+            "32_768", "+32768", "032768", "32768.", ".5", "inf", "NaN", "1e",
+        ] {
+            let source = format!(
+                r#"{{ "level": [ {{ "capacity": {}, "miss_cost": 1 }} ] }}"#,
+                number
+            );
+            assert!(
+                CacheHierarchy::parse_json(&source, String::new()).is_err(),
+                "Accepted invalid JSON number {}",
+                number
+            );
+        }
+        for source in &[
+            r#"{ "level": [ { "capacity": 32768, "miss_cost": 1 } ] } trailing"#,
+            r#"{ "level": [ { "capacity": 32768, "miss_cost": 1 }, ] "#,
+            r#"{ "level": [ { "capacity": 32768 } ] }"#,
+            r#"{ "level": [ { "capacity": 32768, "miss_cost": 1, "color": "red" } ] }"#,
+            r#"[ { "capacity": 32768, "miss_cost": 1 } ]"#,
+            r#"{ "level": [ { "capacity": "32768", "miss_cost": 1 } ] }"#,
+        ] {
+            assert!(
+                CacheHierarchy::parse_json(source, String::new()).is_err(),
+                "Accepted invalid JSON:\n{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn hierarchies_are_validated() {
+        let mut levels = ZEN3_CACHE_LEVELS.to_vec();
+        levels.swap(0, 1);
+        assert!(matches!(
+            CacheHierarchy::new("Swapped", levels),
+            Err(HierarchyError::ShrinkingCapacity { .. })
+                | Err(HierarchyError::DecreasingMissCost { .. })
+        ));
+        assert!(matches!(
+            CacheHierarchy::new("Empty", Vec::new()),
+            Err(HierarchyError::NoLevels)
+        ));
+    }
+}
@@ -0,0 +1,379 @@
+//! Measured memory access latency as a function of working set size
+
+use super::Cost;
+use std::{
+    fmt::{self, Display},
+    io,
+    path::Path,
+};
+
+/// Memory access latency as a function of working set size
+///
+/// This is the kind of curve that pointer-chasing benchmarks measure: the
+/// latency of a random access into a buffer, as a function of the buffer size.
+/// It is an alternative to the plateaus of per-level miss costs, which are
+/// only an approximation of these measurements.
+///
+/// Between two samples, latency is interpolated linearly with respect to the
+/// logarithm of the working set size, which is how these curves are usually
+/// plotted. Outside of the sampled range, the nearest sample is used.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencyCurve {
+    /// Working set size in bytes and latency of each sample, sorted by
+    /// increasing working set size
+    points: Box<[(usize, f32)]>,
+}
+//
+impl LatencyCurve {
+    /// Build a latency curve from (working set size, latency) samples
+    ///
+    /// Latency should increase somewhere along the curve, otherwise it does
+    /// not tell anything about cache misses.
+    ///
+    pub fn new(points: Vec<(usize, f32)>) -> Result<Self, LatencyCurveError> {
+        if points.len() < 2 {
+            return Err(LatencyCurveError::TooFewPoints);
+        }
+        for (idx, &(bytes, latency)) in points.iter().enumerate() {
+            if bytes == 0 || !latency.is_finite() || latency < 0.0 {
+                return Err(LatencyCurveError::BadPoint { point: idx });
+            }
+            if idx > 0 && bytes <= points[idx - 1].0 {
+                return Err(LatencyCurveError::UnsortedPoints { point: idx });
+            }
+        }
+        if points
+            .iter()
+            .all(|&(_bytes, latency)| latency <= points[0].1)
+        {
+            return Err(LatencyCurveError::FlatCurve);
+        }
+        Ok(Self {
+            points: points.into(),
+        })
+    }
+
+    /// Load a latency curve from a CSV file with one `bytes,latency` sample
+    /// per line, optionally preceded by a header line
+    ///
+    /// Latencies can be expressed in any unit, since only their ratios are
+    /// used. Empty lines and lines starting with `#` are ignored.
+    ///
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Self, LatencyCurveError> {
+        let source = std::fs::read_to_string(path).map_err(LatencyCurveError::Io)?;
+        Self::parse_csv(&source)
+    }
+
+    /// Parse the contents of a CSV file, see `from_csv()`
+    fn parse_csv(source: &str) -> Result<Self, LatencyCurveError> {
+        let mut points = Vec::new();
+        let mut first_line = true;
+        for (idx, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let syntax_error = |message: &str| LatencyCurveError::Syntax {
+                line: idx + 1,
+                message: message.to_owned(),
+            };
+            let mut fields = line.split(',').map(str::trim);
+            let (bytes, latency) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(bytes), Some(latency), None) => (bytes, latency),
+                _ => return Err(syntax_error("expected two comma-separated fields")),
+            };
+            match (bytes.parse::<usize>(), latency.parse::<f32>()) {
+                (Ok(bytes), Ok(latency)) => points.push((bytes, latency)),
+                _ if first_line => {}
+                _ => return Err(syntax_error("expected a size in bytes and a latency")),
+            }
+            first_line = false;
+        }
+        Self::new(points)
+    }
+
+    /// Number of samples
+    pub fn num_points(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Samples of the curve, as (working set size, latency) pairs sorted by
+    /// increasing working set size
+    pub fn points(&self) -> &[(usize, f32)] {
+        &self.points[..]
+    }
+
+    /// Write this latency curve in the CSV format of `from_csv()`
+    pub fn to_csv(&self) -> String {
+        let mut csv = "bytes,latency\n".to_owned();
+        for &(bytes, latency) in self.points.iter() {
+            csv.push_str(&format!("{},{}\n", bytes, latency));
+        }
+        csv
+    }
+
+    /// Latency of an access to a working set of a certain size in bytes
+    pub fn latency(&self, bytes: usize) -> f32 {
+        let end_idx = self
+            .points
+            .partition_point(|&(point_bytes, _)| point_bytes < bytes);
+        if end_idx == 0 {
+            return self.points[0].1;
+        }
+        if end_idx == self.points.len() {
+            return self.points[end_idx - 1].1;
+        }
+        let (start_bytes, start_latency) = self.points[end_idx - 1];
+        let (end_bytes, end_latency) = self.points[end_idx];
+        let position = (bytes as f32 / start_bytes as f32).log2()
+            / (end_bytes as f32 / start_bytes as f32).log2();
+        start_latency + position * (end_latency - start_latency)
+    }
+
+    /// Latency of the smallest sampled working set, which fits in the L1 cache
+    pub fn hit_latency(&self) -> f32 {
+        self.points[0].1
+    }
+}
+
+/// Error while setting up a latency curve
+#[derive(Debug)]
+pub enum LatencyCurveError {
+    /// There are less than two samples
+    TooFewPoints,
+
+    /// A sample has a zero working set size or an invalid latency
+    BadPoint { point: usize },
+
+    /// Samples are not sorted by strictly increasing working set size
+    UnsortedPoints { point: usize },
+
+    /// Latency never increases beyond that of the first sample
+    FlatCurve,
+
+    /// Latency does not increase between the first sample and a working set
+    /// size which misses the L1 cache, so L1 misses cannot be calibrated
+    NoL1Miss { l1_miss_bytes: usize },
+
+    /// Once normalized, the largest latency is out of Cost range
+    LatencyOutOfRange,
+
+    /// The CSV file could not be read
+    Io(io::Error),
+
+    /// The CSV file is not correctly formatted
+    Syntax { line: usize, message: String },
+}
+//
+impl Display for LatencyCurveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooFewPoints => write!(f, "there should be at least two samples"),
+            Self::BadPoint { point } => write!(
+                f,
+                "sample {} should have a nonzero size and a finite nonnegative latency",
+                point + 1
+            ),
+            Self::UnsortedPoints { point } => write!(
+                f,
+                "sample {} should have a larger working set than the previous one",
+                point + 1
+            ),
+            Self::FlatCurve => write!(f, "latency should increase with the working set size"),
+            Self::NoL1Miss { l1_miss_bytes } => write!(
+                f,
+                "latency should increase between the first sample and {} bytes, which misses the L1 cache",
+                l1_miss_bytes
+            ),
+            Self::LatencyOutOfRange => write!(
+                f,
+                "the largest latency is too many L1 miss latencies for our Cost type"
+            ),
+            Self::Io(error) => write!(f, "failed to read latency curve file: {}", error),
+            Self::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+//
+impl std::error::Error for LatencyCurveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Latency curve, normalized into the cost units of a cache model
+#[derive(Clone, Debug)]
+pub(super) struct NormalizedCurve {
+    /// Measured latency curve
+    curve: LatencyCurve,
+
+    /// Conversion factor from curve latency units to Cost units
+    scale: f32,
+}
+//
+impl NormalizedCurve {
+    /// Normalize a latency curve, given a working set size in bytes beyond
+    /// the L1 cache capacity, and the latency cost of missing the L1 cache
+    /// (which should be the curve's latency there, minus the L1 hit latency)
+    pub fn new(
+        curve: LatencyCurve,
+        l1_miss_bytes: usize,
+        l1_miss_latency: f32,
+    ) -> Result<Self, LatencyCurveError> {
+        let curve_l1_miss_latency = curve.latency(l1_miss_bytes) - curve.hit_latency();
+        if curve_l1_miss_latency <= 0.0 {
+            return Err(LatencyCurveError::NoL1Miss { l1_miss_bytes });
+        }
+        let result = Self {
+            scale: l1_miss_latency / curve_l1_miss_latency,
+            curve,
+        };
+
+        // Interpolated latencies never exceed the largest sampled latency, so
+        // if that one is in Cost range, every latency cost is
+        let max_latency = result
+            .curve
+            .points()
+            .iter()
+            .map(|&(_bytes, latency)| latency)
+            .fold(0.0, f32::max);
+        Cost::checked_from_num(result.scaled_latency(max_latency))
+            .ok_or(LatencyCurveError::LatencyOutOfRange)?;
+        Ok(result)
+    }
+
+    /// Latency cost of an access whose reuse distance is some amount of bytes
+    pub fn latency_cost(&self, bytes: usize) -> Cost {
+        // Cannot saturate, as checked by new()
+        Cost::saturating_from_num(self.scaled_latency(self.curve.latency(bytes)))
+    }
+
+    /// Lowest latency cost of an access whose reuse distance is at least some
+    /// amount of bytes
+    ///
+    /// Latencies are interpolated monotonically between sampled points, so
+    /// this is reached either at that reuse distance or at a later point.
+    ///
+    pub fn min_latency_cost(&self, min_bytes: usize) -> Cost {
+        self.curve
+            .points()
+            .iter()
+            .filter(|&&(bytes, _latency)| bytes >= min_bytes)
+            .map(|&(bytes, _latency)| self.latency_cost(bytes))
+            .fold(self.latency_cost(min_bytes), Cost::min)
+    }
+
+    /// Convert a latency from the curve into Cost units, minus the L1 hit
+    /// latency
+    fn scaled_latency(&self, latency: f32) -> f32 {
+        (latency - self.curve.hit_latency()).max(0.0) * self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Curve with a 1ns L1 hit latency, and 5ns at 64 KiB and beyond
+    fn test_curve() -> LatencyCurve {
+        LatencyCurve::new(vec![(1024, 1.0), (16 * 1024, 1.0), (64 * 1024, 5.0)]).unwrap()
+    }
+
+    #[test]
+    fn csv_accepts_samples() {
+        let curve = LatencyCurve::parse_csv(
+            "bytes,latency\n\
+             # Comment\n\
+             \n\
+             1024, 1.0\n\
+             16384,1\n\
+             65536 ,5\n",
+        )
+        .unwrap();
+        assert_eq!(curve, test_curve());
+        assert_eq!(LatencyCurve::parse_csv(&curve.to_csv()).unwrap(), curve);
+    }
+
+    #[test]
+    fn csv_rejects_malformed_input() {
+        for source in &[
+            "1024,1\n16384\n",
+            "1024,1\n16384,2,3\n",
+            "1024,1\n16384,fast\n",
+            "1024,1\n-16384,2\n",
+            "1024,1\n",
+            "1024,1\n512,2\n",
+            "1024,1\n2048,1\n",
+            "0,1\n1024,2\n",
+            "1024,1\n2048,NaN\n",
+        ] {
+            assert!(
+                LatencyCurve::parse_csv(source).is_err(),
+                "Accepted invalid latency curve:\n{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn latency_is_interpolated() {
+        let curve = test_curve();
+        for &(bytes, latency) in curve.points() {
+            assert_eq!(curve.latency(bytes), latency);
+        }
+        assert_eq!(curve.latency(1), 1.0);
+        assert_eq!(curve.latency(1 << 30), 5.0);
+        assert_eq!(curve.latency(4 * 1024), 1.0);
+        // 32 KiB is halfway between 16 KiB and 64 KiB on a logarithmic scale
+        assert!((curve.latency(32 * 1024) - 3.0).abs() < 1e-5);
+        assert_eq!(curve.hit_latency(), 1.0);
+    }
+
+    #[test]
+    fn min_latency_follows_dips() {
+        // Some measured curves dip after a cache level's latency plateau
+        let curve = LatencyCurve::new(vec![
+            (1024, 1.0),
+            (64 * 1024, 5.0),
+            (256 * 1024, 3.0),
+            (1 << 20, 9.0),
+        ])
+        .unwrap();
+        let normalized = NormalizedCurve::new(curve, 64 * 1024, 1.0).unwrap();
+        assert_eq!(normalized.min_latency_cost(64 * 1024), Cost::from_num(0.5));
+        assert_eq!(
+            normalized.min_latency_cost(512 * 1024),
+            Cost::from_num(1.25)
+        );
+    }
+
+    #[test]
+    fn curve_is_normalized() {
+        // The L1 miss latency at 64 KiB becomes 0.5 cost units
+        let normalized = NormalizedCurve::new(test_curve(), 64 * 1024, 0.5).unwrap();
+        assert_eq!(normalized.latency_cost(1024), Cost::from_num(0));
+        assert_eq!(normalized.latency_cost(32 * 1024), Cost::from_num(0.25));
+        assert_eq!(normalized.latency_cost(1 << 30), Cost::from_num(0.5));
+
+        // The curve only increases, so its lowest latency is at the start
+        assert_eq!(normalized.min_latency_cost(32 * 1024), Cost::from_num(0.25));
+        assert_eq!(normalized.min_latency_cost(0), Cost::from_num(0));
+
+        // Curves that are flat up to the L1 miss working set are rejected
+        assert!(matches!(
+            NormalizedCurve::new(test_curve(), 16 * 1024, 0.5),
+            Err(LatencyCurveError::NoL1Miss { .. })
+        ));
+
+        // So are curves whose latencies are out of Cost range
+        let steep = LatencyCurve::new(vec![(1024, 1.0), (2048, 1.001), (4096, 1000.0)]).unwrap();
+        assert!(matches!(
+            NormalizedCurve::new(steep, 2048, 1.0),
+            Err(LatencyCurveError::LatencyOutOfRange)
+        ));
+    }
+}
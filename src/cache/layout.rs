@@ -0,0 +1,136 @@
+//! Layout of the feed buffers in memory
+
+use super::Entry;
+use crate::FeedIdx;
+use std::ops::Range;
+
+/// Layout of the feed buffers in memory
+///
+/// Feed buffers are laid out at a regular stride, which is at least the size
+/// of a feed entry. Feeds may be split into two sets, as when correlating the
+/// feeds of one set with those of another set, in which case the entries of
+/// the second set may have a different size. The buffers of the second set
+/// are then laid out contiguously, right after those of the first set.
+///
+#[derive(Clone, Debug)]
+pub struct FeedLayout {
+    /// Number of feeds, whose entries are numbered from 0 to num_feeds - 1
+    num_feeds: FeedIdx,
+
+    /// Size of the entries of the first feed set in bytes
+    entry_size: usize,
+
+    /// Distance between the start of the buffers of successive feeds of the
+    /// first feed set in bytes
+    feed_stride: usize,
+
+    /// Second feed set, if any
+    second_set: Option<SecondFeedSet>,
+}
+//
+impl FeedLayout {
+    /// Lay out `num_feeds` feed buffers at a regular stride, that is
+    /// `feed_stride` bytes separate the start of the buffer of a feed from that
+    /// of the next feed. This stride must be at least `entry_size`.
+    pub fn new(num_feeds: FeedIdx, entry_size: usize, feed_stride: usize) -> Self {
+        assert!(feed_stride >= entry_size, "Feed buffers should not overlap");
+        Self {
+            num_feeds,
+            entry_size,
+            feed_stride,
+            second_set: None,
+        }
+    }
+
+    /// Make the feeds from `first_feed` onwards a second feed set, whose
+    /// entries are `entry_size` bytes large (by default, there is only one
+    /// feed set)
+    pub fn with_second_set(self, first_feed: FeedIdx, entry_size: usize) -> Self {
+        assert!(
+            first_feed > 0 && first_feed < self.num_feeds,
+            "Both feed sets should contain feeds"
+        );
+        assert!(entry_size > 0, "Feed entries should not be empty");
+        Self {
+            second_set: Some(SecondFeedSet {
+                first_feed,
+                entry_size,
+            }),
+            ..self
+        }
+    }
+
+    /// Query the number of feeds
+    pub fn num_feeds(&self) -> FeedIdx {
+        self.num_feeds
+    }
+
+    /// Tell whether all feed entries have the same size
+    pub fn is_uniform(&self) -> bool {
+        self.second_set
+            .iter()
+            .all(|second_set| second_set.entry_size == self.entry_size)
+    }
+
+    /// Number of feed sets
+    pub fn num_sets(&self) -> usize {
+        1 + self.second_set.is_some() as usize
+    }
+
+    /// Index of the feed set that an entry belongs to
+    #[inline(always)]
+    pub fn feed_set(&self, entry: Entry) -> usize {
+        self.second_set
+            .as_ref()
+            .map_or(0, |second_set| (entry >= second_set.first_feed) as usize)
+    }
+
+    /// Size of the entries of a feed set in bytes
+    pub fn set_entry_size(&self, feed_set: usize) -> usize {
+        match (feed_set, &self.second_set) {
+            (0, _) => self.entry_size,
+            (1, Some(second_set)) => second_set.entry_size,
+            _ => panic!("No feed set {}", feed_set),
+        }
+    }
+
+    /// Size of an entry in bytes
+    #[inline(always)]
+    pub fn entry_size(&self, entry: Entry) -> usize {
+        match &self.second_set {
+            Some(second_set) if entry >= second_set.first_feed => second_set.entry_size,
+            _ => self.entry_size,
+        }
+    }
+
+    /// Size of the largest entries in bytes
+    pub fn max_entry_size(&self) -> usize {
+        (0..self.num_sets())
+            .map(|feed_set| self.set_entry_size(feed_set))
+            .max()
+            .unwrap()
+    }
+
+    /// Range of memory addresses that an entry covers, relative to the start
+    /// of the first feed buffer
+    pub fn entry_bytes(&self, entry: Entry) -> Range<usize> {
+        let start = match &self.second_set {
+            Some(second_set) if entry >= second_set.first_feed => {
+                second_set.first_feed as usize * self.feed_stride
+                    + (entry - second_set.first_feed) as usize * second_set.entry_size
+            }
+            _ => entry as usize * self.feed_stride,
+        };
+        start..start + self.entry_size(entry)
+    }
+}
+
+/// Second feed set of a `FeedLayout`
+#[derive(Clone, Debug)]
+struct SecondFeedSet {
+    /// First feed of the set, which goes on until the last feed
+    first_feed: FeedIdx,
+
+    /// Size of the entries of this set in bytes
+    entry_size: usize,
+}
@@ -0,0 +1,77 @@
+//! Memory-level parallelism model
+
+use super::Cost;
+use crate::capacity::CompactUint;
+
+/// Memory-level parallelism configuration
+///
+/// Out-of-order CPU cores do not wait for a cache miss to be resolved before
+/// issuing the next independent memory accesses, so the latencies of nearby
+/// cache misses overlap. Feed accesses are independent from each other, so
+/// this happens both within a feed pair and across successive feed pairs,
+/// within the limits of the core's instruction window.
+///
+/// We model this by grouping cache misses that occur within a window of feed
+/// pairs, up to a maximal number of misses per group. Only the first miss of a
+/// group is charged its full latency, subsequent misses are charged the time
+/// it takes to transfer their data, plus the amount by which their latency
+/// exceeds that of the group's slowest previous miss.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryParallelism {
+    /// Maximal number of cache misses whose latencies overlap
+    pub max_misses: u8,
+
+    /// Number of feed pairs within which cache misses can overlap
+    pub window: usize,
+}
+//
+impl Default for MemoryParallelism {
+    fn default() -> Self {
+        Self {
+            max_misses: 2,
+            window: 1,
+        }
+    }
+}
+
+/// Group of overlapping cache misses that is currently being formed
+#[derive(Clone, Copy, Default)]
+pub(super) struct MissGroup<Clock: CompactUint> {
+    /// Clock of the first miss of the group
+    start: Clock,
+
+    /// Number of misses in the group, or 0 if there is no group yet
+    misses: u8,
+
+    /// Longest miss latency in the group
+    slowest_latency: Cost,
+}
+//
+impl<Clock: CompactUint> MissGroup<Clock> {
+    /// Observe a cache miss of some latency at some clock time, given how many
+    /// clock ticks each feed pair spans, and tell the latency of the slowest
+    /// miss that it overlaps with, if any
+    pub fn observe(
+        &mut self,
+        config: &MemoryParallelism,
+        ticks_per_pair: usize,
+        clock: Clock,
+        latency: Cost,
+    ) -> Option<Cost> {
+        let in_window = clock.to_usize() - self.start.to_usize() < ticks_per_pair * config.window;
+        if self.misses > 0 && self.misses < config.max_misses && in_window {
+            let slowest_latency = self.slowest_latency;
+            self.misses += 1;
+            self.slowest_latency = slowest_latency.max(latency);
+            Some(slowest_latency)
+        } else {
+            *self = Self {
+                start: clock,
+                misses: 1,
+                slowest_latency: latency,
+            };
+            None
+        }
+    }
+}
@@ -0,0 +1,1797 @@
+//! Minimal cache simulator for 2D iteration locality studies
+
+pub mod calibrate;
+mod cost;
+pub mod hierarchy;
+pub mod latency_curve;
+mod layout;
+mod mlp;
+pub mod multicore;
+mod prefetch;
+mod recency;
+pub mod replacement;
+mod stack_distance;
+mod stats;
+mod sysfs;
+mod tlb;
+pub mod trace;
+
+pub use self::{
+    cost::{CostAccumulator, CostOverflow, WideCost},
+    hierarchy::{CacheHierarchy, CacheLevel, HierarchyError, Inclusion, SetAssociativity},
+    latency_curve::{LatencyCurve, LatencyCurveError},
+    layout::FeedLayout,
+    mlp::MemoryParallelism,
+    multicore::MultiCoreModel,
+    prefetch::Prefetcher,
+    stack_distance::StackDistanceHistogram,
+    stats::CacheStats,
+    tlb::Tlb,
+    trace::{Trace, TraceFormat},
+};
+
+use self::{
+    latency_curve::NormalizedCurve,
+    mlp::MissGroup,
+    prefetch::PrefetchStreams,
+    recency::RecencyIndex,
+    replacement::{CacheSetState, Lru, ReplacementPolicy},
+    tlb::TlbModel,
+};
+use crate::{
+    capacity::{div_round_up, CompactUint, FeedCapacity, FeedClocks, LargestCapacity},
+    FeedIdx,
+};
+use fixed::{types::extra::U2, FixedU16};
+use num_traits::identities::Zero;
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    ops::Range,
+    path::Path,
+    rc::Rc,
+};
+
+/// In our simplified cache model, radio feeds are indivisible cache entities
+pub type Entry = FeedIdx;
+
+/// Operation cache cost is modeled using a very small fixed-point type to keep
+/// PartialPathData as small as possible. Costs can be accumulated into wider
+/// types using the `CostAccumulator` trait.
+pub type Cost = FixedU16<U2>;
+const COST_GRANULARITY: u16 = 1 << 2;
+
+/// Cost of an L1 cache miss, which is the unit in which cache costs are
+/// expressed in order to save precious fixed-point bits.
+pub const L1_MISS_COST: Cost = Cost::from_bits(COST_GRANULARITY);
+
+/// Cost of accessing a cache entry that was never accessed before
+///
+/// This is somewhat artificial (we don't really know what the cost will be, it
+/// depends on previous program execution), but adding a small cost here helps
+/// hinting optimal path search algorithms towards making the most of previously
+/// loaded cache entries before loading new ones.
+///
+/// The current cost is tuned such that the algorithm is wary of introducing a
+/// new cache entry, but not to the point of not doing so when it can avoid a
+/// cache miss or two that way.
+///
+pub const NEW_ENTRY_COST: Cost = Cost::from_bits(COST_GRANULARITY / 4);
+
+/// Minimum cache cost of accessing N entries, in units of L1 cache misses
+pub fn min_cache_cost(num_entries: Entry) -> Cost {
+    Cost::from_num(num_entries) * NEW_ENTRY_COST / L1_MISS_COST
+}
+
+/// Cost of a memory access, split between the cost of reading data into the
+/// cache hierarchy and the cost of writing modified data out of it
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AccessCost {
+    /// Cost of cache misses
+    pub read: Cost,
+
+    /// Cost of write-backs and streaming stores
+    pub write: Cost,
+}
+//
+impl AccessCost {
+    /// Total cost of the access, if it is within Cost range
+    pub fn total(&self) -> Result<Cost, CostOverflow> {
+        let mut total = self.read;
+        total.add_cost(self.write)?;
+        Ok(total)
+    }
+}
+
+/// How output entries are written to memory
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputStores {
+    /// Output entries are allocated into the cache hierarchy like any other
+    /// data, and written back to memory when they are evicted
+    WriteAllocate,
+
+    /// Output entries are written using non-temporal (streaming) stores, which
+    /// bypass the cache hierarchy but consume memory bandwidth on every write
+    NonTemporal,
+}
+
+/// Combination of cache model features that cannot be simulated together
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnsupportedFeature {
+    /// Write-allocated output entries with a replacement policy or inclusion
+    /// mode that requires explicitly simulating cache sets
+    WriteAllocatedOutputs,
+
+    /// Feed entries of distinct sizes with a replacement policy or inclusion
+    /// mode that requires explicitly simulating cache sets of unknown geometry
+    MixedEntrySizes,
+
+    /// Several threads with a replacement policy or inclusion mode that
+    /// requires explicitly simulating cache sets
+    MultiCore,
+}
+//
+impl Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::WriteAllocatedOutputs => write!(
+                f,
+                "write-allocated output entries require LRU-like replacement and \
+                 inclusive (or fully associative exclusive) cache levels, consider \
+                 using non-temporal stores"
+            ),
+            Self::MixedEntrySizes => write!(
+                f,
+                "feed entries of distinct sizes require LRU-like replacement and \
+                 inclusive (or fully associative exclusive) cache levels, unless \
+                 every cache level has a known geometry"
+            ),
+            Self::MultiCore => write!(
+                f,
+                "multi-core simulation requires LRU-like replacement and inclusive \
+                 (or fully associative exclusive) cache levels"
+            ),
+        }
+    }
+}
+//
+impl std::error::Error for UnsupportedFeature {}
+
+/// CPU cache model, used for evaluating locality merits of 2D iteration schemes
+#[derive(Clone, Debug)]
+pub struct CacheModel {
+    // Cache levels, from the closest to the CPU to the farthest from it
+    levels: Box<[CacheLevel]>,
+
+    // Layout of the feed buffers in memory
+    layout: FeedLayout,
+
+    // Cost of missing each cache level, in units of L1 cache misses, for
+    // entries of the first feed set
+    miss_costs: Box<[Cost]>,
+
+    // Cost of missing each cache level when the prefetcher correctly predicted
+    // the access, which leaves only the cost of transferring the data, for
+    // entries of each feed set
+    prefetched_miss_costs: Box<[Box<[Cost]>]>,
+
+    // Cost of an L1 cache miss for entries of the first feed set, in units of
+    // L1 cache miss latency
+    cost_unit: f32,
+
+    // Capacity of each cache level in entries, if they all had the size of the
+    // largest entries
+    level_entries: Box<[usize]>,
+
+    // Conflict model of each cache level, if it is simulated as a
+    // set-associative cache rather than a fully associative one
+    set_conflicts: Box<[Option<SetConflicts>]>,
+
+    // Replacement policy of all cache levels
+    replacement_policy: Rc<dyn ReplacementPolicy>,
+
+    // Relationship between the contents of successive cache levels
+    inclusion: Inclusion,
+
+    // Hardware prefetcher, if any
+    prefetcher: Option<Prefetcher>,
+
+    // Memory-level parallelism, if cache miss latencies may overlap
+    memory_parallelism: Option<MemoryParallelism>,
+
+    // Measured latency curve, if it replaces the miss latency of each level
+    latency_curve: Option<NormalizedCurve>,
+
+    // Per-pair output entries, if they are modeled
+    outputs: Option<OutputEntries>,
+
+    // How output entries are written to memory
+    output_stores: OutputStores,
+
+    // Translation lookaside buffer, if it is modeled
+    tlb: Option<TlbModel>,
+
+    // Cache sets of each level whose contents must be simulated when the
+    // replacement policy is not a stack algorithm
+    simulated_sets: Box<[Box<[SimulatedSet]>]>,
+}
+//
+impl CacheModel {
+    /// Set up the cache model by telling which cache hierarchy to model, and
+    /// how the buffers of the accessed feeds are laid out in memory
+    ///
+    /// This models fully associative caches, where only capacity misses occur.
+    ///
+    pub fn new(hierarchy: &CacheHierarchy, layout: FeedLayout) -> Self {
+        let set_conflicts = hierarchy.levels.iter().map(|_| None).collect();
+        Self::with_set_conflicts(hierarchy, layout, set_conflicts)
+    }
+
+    /// Set up a fully associative model of one of the named cache hierarchy
+    /// presets, see `CacheHierarchy::from_preset()`
+    #[allow(dead_code)]
+    pub fn from_preset(name: &str, layout: FeedLayout) -> Result<Self, HierarchyError> {
+        Ok(Self::new(&CacheHierarchy::from_preset(name)?, layout))
+    }
+
+    /// Set up a fully associative model of a cache hierarchy that is described
+    /// by a configuration file, see `CacheHierarchy::from_file()`
+    #[allow(dead_code)]
+    pub fn from_file(path: impl AsRef<Path>, layout: FeedLayout) -> Result<Self, HierarchyError> {
+        Ok(Self::new(&CacheHierarchy::from_file(path)?, layout))
+    }
+
+    /// Set up a set-associative cache model, which also accounts for conflict
+    /// misses between feeds whose data maps into the same cache sets
+    ///
+    /// Cache levels whose geometry is unknown are modeled as fully associative.
+    ///
+    pub fn new_set_associative(hierarchy: &CacheHierarchy, layout: FeedLayout) -> Self {
+        let set_conflicts = hierarchy
+            .levels
+            .iter()
+            .map(|level| {
+                level
+                    .associativity
+                    .map(|associativity| SetConflicts::new(associativity, &layout))
+            })
+            .collect();
+        Self::with_set_conflicts(hierarchy, layout, set_conflicts)
+    }
+
+    /// Common part of the fully associative and set-associative constructors
+    fn with_set_conflicts(
+        hierarchy: &CacheHierarchy,
+        layout: FeedLayout,
+        set_conflicts: Box<[Option<SetConflicts>]>,
+    ) -> Self {
+        if let Err(error) = hierarchy.validate() {
+            panic!("Invalid cache hierarchy: {}", error);
+        }
+        let levels = &hierarchy.levels[..];
+        let level_entries = levels
+            .iter()
+            .map(|level| level.capacity / layout.max_entry_size())
+            .collect::<Box<[_]>>();
+
+        // Missing a cache level costs its latency, plus the time it takes to
+        // transfer all the cache lines of the entry. We normalize this by the
+        // cost of an L1 miss for entries of the first feed set, so that bigger
+        // entries do not overflow our tiny fixed-point Cost type, and report
+        // the normalization factor.
+        let transfer_costs = (0..layout.num_sets())
+            .map(|feed_set| {
+                let entry_size = layout.set_entry_size(feed_set);
+                levels
+                    .iter()
+                    .map(|level| level.transfer_cost(entry_size))
+                    .collect::<Box<[_]>>()
+            })
+            .collect::<Box<[_]>>();
+        let cost_unit = levels[0].miss_cost.to_num::<f32>() + transfer_costs[0][0];
+        let normalize = |absolute_cost: f32| {
+            Cost::checked_from_num(absolute_cost / cost_unit)
+                .expect("Cache miss cost is out of Cost range")
+        };
+        let miss_costs = levels
+            .iter()
+            .zip(transfer_costs[0].iter())
+            .map(|(level, &transfer_cost)| {
+                normalize(level.miss_cost.to_num::<f32>() + transfer_cost)
+            })
+            .collect();
+        let prefetched_miss_costs = transfer_costs
+            .iter()
+            .map(|set_transfer_costs| {
+                set_transfer_costs
+                    .iter()
+                    .map(|&transfer_cost| normalize(transfer_cost))
+                    .collect()
+            })
+            .collect();
+
+        let simulated_sets =
+            SimulatedSet::layout(&level_entries, layout.num_feeds(), &set_conflicts);
+        Self {
+            levels: levels.into(),
+            layout,
+            miss_costs,
+            prefetched_miss_costs,
+            cost_unit,
+            level_entries,
+            set_conflicts,
+            replacement_policy: Rc::new(Lru),
+            inclusion: Inclusion::Inclusive,
+            prefetcher: None,
+            memory_parallelism: None,
+            latency_curve: None,
+            outputs: None,
+            output_stores: OutputStores::WriteAllocate,
+            tlb: None,
+            simulated_sets,
+        }
+    }
+
+    /// Use a different replacement policy than the default (LRU)
+    pub fn with_replacement_policy(self, replacement_policy: Rc<dyn ReplacementPolicy>) -> Self {
+        Self {
+            replacement_policy,
+            ..self
+        }
+    }
+
+    /// Use a different hierarchy mode than the default (inclusive)
+    pub fn with_inclusion(self, inclusion: Inclusion) -> Self {
+        Self { inclusion, ..self }
+    }
+
+    /// Model a hardware prefetcher (by default, there is none)
+    pub fn with_prefetcher(self, prefetcher: Option<Prefetcher>) -> Self {
+        Self { prefetcher, ..self }
+    }
+
+    /// Let the latencies of nearby cache misses overlap (by default, each
+    /// cache miss is charged its full latency)
+    pub fn with_memory_parallelism(self, memory_parallelism: Option<MemoryParallelism>) -> Self {
+        Self {
+            memory_parallelism,
+            ..self
+        }
+    }
+
+    /// Derive cache miss latencies from a measured latency curve, evaluated at
+    /// the reuse distance of each access in bytes (by default, each cache
+    /// level's miss cost is used)
+    ///
+    /// The curve is normalized such that the latency of an L1 miss, which is
+    /// measured at twice the L1 capacity, matches the L1 miss cost of the
+    /// cache hierarchy. Data transfer costs are still those of the cache
+    /// level that is hit, which is also used by the prefetcher model.
+    ///
+    /// This fails if the curve does not increase by twice the L1 capacity, or
+    /// if its latencies are out of Cost range once normalized.
+    ///
+    pub fn with_latency_curve(
+        self,
+        latency_curve: Option<LatencyCurve>,
+    ) -> Result<Self, LatencyCurveError> {
+        let latency_curve = latency_curve
+            .map(|curve| {
+                let l1 = &self.levels[0];
+                NormalizedCurve::new(
+                    curve,
+                    2 * l1.capacity,
+                    l1.miss_cost.to_num::<f32>() / self.cost_unit,
+                )
+            })
+            .transpose()?;
+        Ok(Self {
+            latency_curve,
+            ..self
+        })
+    }
+
+    /// Model per-pair output entries of a certain size in bytes, which are
+    /// accessed after the feed entries of each pair (by default, there are
+    /// no output entries)
+    ///
+    /// Since every pair is only visited once, reading an output entry always
+    /// misses every cache level, whatever the iteration order. This read cost
+    /// is the same for every iteration order, so it is left out of simulated
+    /// costs. What depends on the iteration order is the cache capacity that
+    /// output entries steal from feed entries, and when the modified output
+    /// entries are written back to the next cache level, which is accounted
+    /// for on eviction.
+    ///
+    /// Unless output entries are written using non-temporal stores, this is
+    /// only supported when the contents of cache sets can be deduced from the
+    /// order of previous accesses, and output entries are assumed to be spread
+    /// uniformly across the sets of set-associative caches.
+    ///
+    pub fn with_output_entries(self, output_size: Option<usize>) -> Self {
+        let memory_level = self.levels.last().unwrap();
+        let outputs = output_size.map(|size| OutputEntries {
+            size,
+            streaming_store_cost: Cost::checked_from_num(
+                memory_level.transfer_cost(size) / self.cost_unit,
+            )
+            .expect("Streaming store cost is out of Cost range"),
+            writeback_costs: self
+                .levels
+                .iter()
+                .map(|level| {
+                    Cost::checked_from_num(level.transfer_cost(size) / self.cost_unit)
+                        .expect("Write-back cost is out of Cost range")
+                })
+                .collect(),
+        });
+        Self { outputs, ..self }
+    }
+
+    /// Select how output entries are written to memory (by default, they are
+    /// allocated into the cache hierarchy)
+    ///
+    /// Non-temporal stores do not steal cache capacity from feed entries and
+    /// are never written back, but every output entry then costs the memory
+    /// bandwidth needed to transfer it, whether or not it would have stayed in
+    /// cache until the end of the iteration.
+    ///
+    pub fn with_output_stores(self, output_stores: OutputStores) -> Self {
+        Self {
+            output_stores,
+            ..self
+        }
+    }
+
+    /// Model a translation lookaside buffer (by default, there is none)
+    pub fn with_tlb(self, tlb: Option<Tlb>) -> Self {
+        let tlb = tlb.map(|tlb| TlbModel::new(&tlb, &self.layout, self.cost_unit));
+        Self { tlb, ..self }
+    }
+
+    /// Output entries that are allocated into the cache hierarchy, if any
+    fn cached_outputs(&self) -> Option<&OutputEntries> {
+        match self.output_stores {
+            OutputStores::WriteAllocate => self.outputs.as_ref(),
+            OutputStores::NonTemporal => None,
+        }
+    }
+
+    /// Tell whether the contents of cache sets must be explicitly simulated,
+    /// instead of being deduced from the order of previous accesses
+    ///
+    /// This is needed when the replacement policy is not a stack algorithm,
+    /// and when levels do not all see the same accesses. The exception is
+    /// fully associative exclusive hierarchies, which behave like a single
+    /// fully associative cache whose capacity is the sum of level capacities.
+    ///
+    fn simulates_sets(&self) -> bool {
+        !self.replacement_policy.is_stack_algorithm()
+            || match self.inclusion {
+                Inclusion::Inclusive => false,
+                Inclusion::Exclusive => self.set_conflicts.iter().any(Option::is_some),
+                Inclusion::NonInclusive => true,
+            }
+    }
+
+    /// Check that the features of this cache model can be simulated together
+    ///
+    /// Some features are only supported when the contents of cache sets can be
+    /// deduced from the order of previous accesses, see `simulates_sets()`.
+    /// Simulations of cache models which fail this check panic on startup.
+    ///
+    pub fn check_features(&self) -> Result<(), UnsupportedFeature> {
+        let simulates_sets = self.simulates_sets();
+        if self.cached_outputs().is_some() && simulates_sets {
+            return Err(UnsupportedFeature::WriteAllocatedOutputs);
+        }
+        if !self.layout.is_uniform()
+            && simulates_sets
+            && !self.set_conflicts.iter().all(Option::is_some)
+        {
+            return Err(UnsupportedFeature::MixedEntrySizes);
+        }
+        Ok(())
+    }
+
+    /// Query the number of feeds
+    pub fn num_feeds(&self) -> FeedIdx {
+        self.layout.num_feeds()
+    }
+
+    /// Query the layout of the feed buffers in memory
+    pub fn layout(&self) -> &FeedLayout {
+        &self.layout
+    }
+
+    /// Query the number of L1 cache entries, if they all had the size of the
+    /// largest entries
+    pub(crate) fn max_l1_entries(&self) -> usize {
+        self.level_entries[0]
+    }
+
+    /// Query the cost of an L1 cache miss for entries of the first feed set,
+    /// which is the cheapest cache miss for these entries
+    pub fn l1_miss_cost(&self) -> Cost {
+        self.miss_costs[0]
+    }
+
+    /// Query the lowest cost that reloading a previously accessed entry into
+    /// the L1 cache can have, in units of L1 cache misses
+    ///
+    /// This is a lower bound on the extra cost of a path that must reload some
+    /// entry. A correctly prefetched miss only costs the transfer of its data,
+    /// and so can a miss whose latency overlaps with that of a previous miss.
+    ///
+    /// Latency curves make a miss cheaper than an L1 miss when its reuse
+    /// distance is close to the L1 capacity, or below it for conflict misses
+    /// and replacement policies that are not stack algorithms.
+    ///
+    pub(crate) fn min_reload_cost(&self) -> Cost {
+        let min_transfer_cost = self
+            .prefetched_miss_costs
+            .iter()
+            .flat_map(|set_costs| set_costs.iter().copied())
+            .min()
+            .unwrap_or_else(Cost::zero);
+        let min_latency = if self.prefetcher.is_some() || self.memory_parallelism.is_some() {
+            Cost::zero()
+        } else {
+            let min_level_latency = self
+                .miss_costs
+                .iter()
+                .zip(self.prefetched_miss_costs[0].iter())
+                .map(|(&miss_cost, &transfer_cost)| miss_cost - transfer_cost)
+                .min()
+                .unwrap_or_else(Cost::zero);
+            self.latency_curve
+                .as_ref()
+                .map_or(min_level_latency, |latency_curve| {
+                    let only_capacity_misses =
+                        !self.simulates_sets() && self.set_conflicts[0].is_none();
+                    let min_reuse_bytes = if only_capacity_misses {
+                        self.levels[0].capacity + 1
+                    } else {
+                        0
+                    };
+                    min_level_latency.min(latency_curve.min_latency_cost(min_reuse_bytes))
+                })
+        };
+        min_transfer_cost.saturating_add(min_latency)
+    }
+
+    /// Query the cost of an L1 cache miss for entries of the first feed set,
+    /// in units of L1 cache miss latency
+    ///
+    /// Since entries span multiple cache lines, this is more than one. It can
+    /// be used to compare costs between models with different entry sizes.
+    ///
+    pub fn cost_unit(&self) -> f32 {
+        self.cost_unit
+    }
+
+    /// Tell how expensive it would be to access an entry (in units of L1 cache
+    /// miss costs, with L1 hits considered free), given whether it is the first
+    /// access to that entry, which cache level held it (None if only the
+    /// main memory did), the latency of a cache miss, and how much of it is
+    /// hidden by the prefetcher or by overlapping cache misses.
+    fn cost_model(
+        &self,
+        entry: Entry,
+        first_access: bool,
+        hit_level: Option<usize>,
+        latency: Cost,
+        hidden_latency: Cost,
+    ) -> Result<Cost, CostOverflow> {
+        if first_access {
+            return Ok(NEW_ENTRY_COST);
+        }
+        match self.miss_level(hit_level) {
+            Some(miss_level) => {
+                let feed_set = self.layout.feed_set(entry);
+                let mut cost = self.prefetched_miss_costs[feed_set][miss_level];
+                cost.add_cost(latency.saturating_sub(hidden_latency))?;
+                Ok(cost)
+            }
+            None => Ok(Cost::zero()),
+        }
+    }
+
+    /// Tell which cache level was missed last, given which level held an entry
+    /// (None if only the main memory did), or None if no level was missed
+    fn miss_level(&self, hit_level: Option<usize>) -> Option<usize> {
+        match hit_level {
+            Some(0) => None,
+            Some(level) => Some(level - 1),
+            None => Some(self.levels.len() - 1),
+        }
+    }
+
+    /// Latency part of the cost of missing some cache level, given the reuse
+    /// distance of the access in bytes, if known
+    fn miss_latency(&self, miss_level: usize, reuse_bytes: Option<usize>) -> Cost {
+        match (&self.latency_curve, reuse_bytes) {
+            (Some(latency_curve), Some(reuse_bytes)) => latency_curve.latency_cost(reuse_bytes),
+            _ => self.miss_costs[miss_level] - self.prefetched_miss_costs[0][miss_level],
+        }
+    }
+
+    /// Number of clock ticks that each feed pair spans in cache simulations
+    fn ticks_per_pair(&self) -> usize {
+        if self.outputs.is_some() {
+            3
+        } else {
+            2
+        }
+    }
+
+    /// Find the first cache level that holds a previously accessed entry,
+    /// under the assumption that the replacement policy is a stack algorithm
+    /// and that cache set contents need not be explicitly simulated.
+    fn stack_hit_level<F: FeedCapacity>(
+        &self,
+        sim: &CacheSimulation<F>,
+        entry: Entry,
+    ) -> Option<usize> {
+        // Fully associative caches only need to know how many other entries
+        // were accessed since the last access, set-associative caches need to
+        // know which entries were accessed.
+        //
+        // Output entries, if any, are accounted for in bytes, because they do
+        // not have the same size as feed entries. So are feed entries, if
+        // they do not all have the same size.
+        let mut age = None;
+        let mut reuse_bytes = None;
+        let outputs_since = sim.outputs_since(sim.last_access(entry));
+        let mut cumulative_entries = 0;
+        let mut cumulative_bytes = 0;
+        let mut next_set_group_weight = FIRST_SET_GROUP_WEIGHT;
+        (0..self.levels.len()).position(|level| {
+            if let Some(set_conflicts) = &self.set_conflicts[level] {
+                let output_lines = self.cached_outputs().map_or(0, |outputs| {
+                    outputs_since * outputs.size.div_ceil(set_conflicts.line_size)
+                });
+                let first_weight = next_set_group_weight;
+                next_set_group_weight += set_conflicts.num_groups();
+                set_conflicts.holds(entry, output_lines, |group, lines_per_feed| {
+                    sim.weight_accessed_since(entry, first_weight + group, |other| {
+                        lines_per_feed[other as usize]
+                    })
+                })
+            } else {
+                // Exclusive levels extend the capacity of the levels above
+                cumulative_entries += self.level_entries[level];
+                cumulative_bytes += self.levels[level].capacity;
+                let (capacity_entries, capacity_bytes) = if self.inclusion == Inclusion::Exclusive {
+                    (cumulative_entries, cumulative_bytes)
+                } else {
+                    (self.level_entries[level], self.levels[level].capacity)
+                };
+                if self.cached_outputs().is_some() || !self.layout.is_uniform() {
+                    let reuse_bytes =
+                        *reuse_bytes.get_or_insert_with(|| sim.reuse_bytes(self, entry).unwrap());
+                    reuse_bytes <= capacity_bytes
+                } else {
+                    let age = *age.get_or_insert_with(|| sim.age(entry).unwrap());
+                    age < capacity_entries
+                }
+            }
+        })
+    }
+
+    /// Capacity of each cache level in bytes, accounting for the capacity of
+    /// the levels above it in exclusive hierarchies
+    fn effective_capacities(&self) -> impl Iterator<Item = usize> + '_ {
+        let exclusive = self.inclusion == Inclusion::Exclusive;
+        self.levels
+            .iter()
+            .scan(0, move |cumulative_capacity, level| {
+                *cumulative_capacity += level.capacity;
+                Some(if exclusive {
+                    *cumulative_capacity
+                } else {
+                    level.capacity
+                })
+            })
+    }
+
+    /// Per-entry weights that simulations sum over the entries which were
+    /// accessed since another entry: entry sizes (see `ENTRY_SIZE_WEIGHT`),
+    /// then the number of cache lines that each entry holds in each set group
+    /// of each set-associative level, in level order
+    fn accessed_since_weights(&self) -> Vec<Box<[usize]>> {
+        let entry_sizes = (0..self.num_feeds())
+            .map(|feed| self.layout.entry_size(feed))
+            .collect();
+        std::iter::once(entry_sizes)
+            .chain(
+                self.set_conflicts
+                    .iter()
+                    .flatten()
+                    .flat_map(|set_conflicts| set_conflicts.set_groups())
+                    .map(Box::from),
+            )
+            .collect()
+    }
+
+    /// Start a cache simulation, which can track a certain maximal number of
+    /// feeds
+    pub fn start_simulation<F: FeedCapacity>(&self) -> CacheSimulation<F> {
+        CacheSimulation::new(self)
+    }
+}
+
+/// Per-pair output entries
+#[derive(Clone, Debug)]
+struct OutputEntries {
+    /// Size of an output entry in bytes
+    size: usize,
+
+    /// Cost of writing back an output entry from each cache level to the next
+    writeback_costs: Box<[Cost]>,
+
+    /// Cost of writing an output entry to memory with non-temporal stores
+    streaming_store_cost: Cost,
+}
+
+/// Footprints of the write-allocated output entries that may still be evicted
+/// from some cache level, from the oldest to the most recent one
+///
+/// The footprint of an output entry is the number of bytes of feed and output
+/// entries that were accessed since it was accessed, including itself. It can
+/// only grow, so older output entries have larger footprints, and output
+/// entries can be forgotten once their footprint exceeds every cache capacity.
+/// Write-back accounting thus only looks at the output entries that fit in
+/// cache, instead of every output entry that was accessed so far.
+///
+#[derive(Clone, Default)]
+struct OutputFootprints(VecDeque<OutputFootprint>);
+//
+impl OutputFootprints {
+    /// Account for an access to a feed entry of a certain size in bytes, given
+    /// when this entry was last accessed (0 if it was never accessed)
+    fn record_feed_access(&mut self, model: &CacheModel, last_access_time: usize, size: usize) {
+        for footprint in self
+            .0
+            .iter_mut()
+            .rev()
+            .take_while(|footprint| footprint.access_time > last_access_time)
+        {
+            footprint.bytes += size;
+        }
+        self.forget_evicted(model);
+    }
+
+    /// Account for an access to a new output entry of a certain size in bytes
+    fn record_output_access(&mut self, model: &CacheModel, access_time: usize, size: usize) {
+        for footprint in self.0.iter_mut() {
+            footprint.bytes += size;
+        }
+        self.0.push_back(OutputFootprint {
+            access_time,
+            bytes: size,
+        });
+        self.forget_evicted(model);
+    }
+
+    /// Forget about the output entries that were evicted from every cache level
+    fn forget_evicted(&mut self, model: &CacheModel) {
+        let max_capacity = model.effective_capacities().max().unwrap_or(0);
+        while matches!(self.0.front(), Some(footprint) if footprint.bytes > max_capacity) {
+            self.0.pop_front();
+        }
+    }
+}
+
+/// Footprint of an output entry, see `OutputFootprints`
+#[derive(Clone, Copy)]
+struct OutputFootprint {
+    /// Time at which the output entry was accessed
+    access_time: usize,
+
+    /// Bytes of feed and output entries accessed since then, including the
+    /// output entry itself
+    bytes: usize,
+}
+
+/// Conflict model of a set-associative cache level
+///
+/// Since each feed buffer is contiguous in memory, each feed covers a
+/// contiguous range of cache lines, which wraps around the cache sets. The
+/// cache sets can thus be partitioned into a few groups (at most two per feed),
+/// such that within each group, every feed holds the same number of cache
+/// lines in every set. All sets of a group behave identically, so we only need
+/// to track one representative set per group.
+///
+/// Under an LRU replacement policy, and given that all the cache lines of an
+/// entry are accessed together, an entry is still fully cached if in every set
+/// where it holds cache lines, these lines plus those of the entries which were
+/// accessed since then fit in the cache ways.
+///
+#[derive(Clone, Debug)]
+struct SetConflicts {
+    /// Number of cache lines in each cache set
+    ways: usize,
+
+    /// Number of cache sets
+    sets: usize,
+
+    /// Size of a cache line in bytes
+    line_size: usize,
+
+    /// Number of feeds
+    num_feeds: usize,
+
+    /// Number of cache lines that each feed holds in each set of each group,
+    /// stored group by group
+    set_groups: Box<[usize]>,
+}
+//
+impl SetConflicts {
+    /// Compute the conflict model of a cache level for a certain layout of
+    /// feed buffers in memory
+    fn new(associativity: SetAssociativity, layout: &FeedLayout) -> Self {
+        let SetAssociativity {
+            ways,
+            sets,
+            line_size,
+        } = associativity;
+
+        // Determine the first cache set and number of cache lines of each feed
+        let num_feeds = layout.num_feeds();
+        let footprints = (0..num_feeds)
+            .map(|feed| {
+                let bytes = layout.entry_bytes(feed);
+                let first_line = bytes.start / line_size;
+                let end_line = bytes.end.div_ceil(line_size);
+                (first_line % sets, end_line - first_line)
+            })
+            .collect::<Box<[_]>>();
+
+        // Sets where one feed starts or stops are the boundaries of set groups
+        let mut group_starts = Vec::with_capacity(2 * footprints.len());
+        for &(first_set, num_lines) in footprints.iter() {
+            group_starts.push(first_set);
+            group_starts.push((first_set + num_lines) % sets);
+        }
+        group_starts.sort_unstable();
+        group_starts.dedup();
+
+        // Count the lines of each feed in the first set of each group
+        let set_groups = group_starts
+            .into_iter()
+            .flat_map(|set| {
+                footprints.iter().map(move |&(first_set, num_lines)| {
+                    let offset = (set + sets - first_set) % sets;
+                    num_lines / sets + (offset < num_lines % sets) as usize
+                })
+            })
+            .collect();
+        Self {
+            ways,
+            sets,
+            line_size,
+            num_feeds: num_feeds as usize,
+            set_groups,
+        }
+    }
+
+    /// Tell whether an entry is still cached, given the number of output
+    /// entry cache lines which were accessed since the last time this entry
+    /// was accessed (these are assumed to be spread uniformly across cache
+    /// sets), and a function that counts the cache lines which the feed
+    /// entries accessed since then hold in each set of a group, given the
+    /// index of that group and the number of lines of each feed in its sets
+    fn holds(
+        &self,
+        entry: Entry,
+        output_lines: usize,
+        mut lines_since: impl FnMut(usize, &[usize]) -> usize,
+    ) -> bool {
+        self.set_groups()
+            .enumerate()
+            .all(|(group, lines_per_feed)| {
+                let entry_lines = lines_per_feed[entry as usize];
+                entry_lines == 0 || {
+                    let feed_lines = entry_lines + lines_since(group, lines_per_feed);
+                    feed_lines * self.sets + output_lines <= self.ways * self.sets
+                }
+            })
+    }
+
+    /// Number of set groups
+    fn num_groups(&self) -> usize {
+        self.set_groups().count()
+    }
+
+    /// Number of cache lines that each feed holds in the sets of each group
+    fn set_groups(&self) -> impl Iterator<Item = &[usize]> {
+        // chunks_exact() panics on zero-sized chunks, and there are no set
+        // groups when there are no feeds anyway
+        self.set_groups.chunks_exact(self.num_feeds.max(1))
+    }
+}
+
+/// Cache set whose contents must be simulated, because the replacement policy
+/// is not a stack algorithm
+#[derive(Clone, Debug)]
+struct SimulatedSet {
+    /// Index of this set's metadata in the simulation state
+    index: usize,
+
+    /// Index of this set's first way in the simulation state
+    first_way: usize,
+
+    /// Number of ways in this set
+    ways: usize,
+
+    /// Number of cache lines that each feed holds in this set
+    lines_per_feed: Box<[usize]>,
+}
+//
+impl SimulatedSet {
+    /// Determine which cache sets must be simulated for each cache level,
+    /// given the capacity of each level in entries and the conflict model of
+    /// each level if caches are set-associative.
+    ///
+    /// A fully associative level is simulated as a single set whose lines are
+    /// whole entries. Since every level sees every access, sets which can hold
+    /// all the lines that map into them never evict anything and do not need
+    /// to be simulated.
+    ///
+    fn layout(
+        capacities: &[usize],
+        num_feeds: FeedIdx,
+        set_conflicts: &[Option<SetConflicts>],
+    ) -> Box<[Box<[Self]>]> {
+        let mut index = 0;
+        let mut first_way = 0;
+        (0..capacities.len())
+            .map(|level| {
+                let groups = if let Some(conflicts) = &set_conflicts[level] {
+                    conflicts
+                        .set_groups()
+                        .map(|lines_per_feed| (conflicts.ways, lines_per_feed.into()))
+                        .collect::<Vec<_>>()
+                } else {
+                    vec![(
+                        capacities[level],
+                        vec![1; num_feeds as usize].into_boxed_slice(),
+                    )]
+                };
+                groups
+                    .into_iter()
+                    .filter(|(ways, lines_per_feed)| lines_per_feed.iter().sum::<usize>() > *ways)
+                    .map(|(ways, lines_per_feed)| {
+                        let set = Self {
+                            index,
+                            first_way,
+                            ways,
+                            lines_per_feed,
+                        };
+                        index += 1;
+                        first_way += ways;
+                        set
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Index of entry sizes in `CacheModel::accessed_since_weights()`
+const ENTRY_SIZE_WEIGHT: usize = 0;
+
+/// Index of the first set group of the first set-associative cache level in
+/// `CacheModel::accessed_since_weights()`
+const FIRST_SET_GROUP_WEIGHT: usize = 1;
+
+/// Number of values beyond which the recency index stops indexing the cache
+/// lines of each entry in each set group, which are too many to track at once
+/// for some set-associative hierarchies and large traces
+const MAX_RECENCY_INDEX_VALUES: usize = 1 << 24;
+
+/// CPU cache simulation
+///
+/// Split from the main CacheModel so that we can efficiently have multiple
+/// cache simulations that efficiently follow the same cache model.
+///
+/// Simulations can track up to a certain number of feeds, and their clock and
+/// per-feed state are kept as small as this number allows, since they are part
+/// of the PartialPathData struct that dominates our memory bandwidth usage.
+///
+#[derive(Clone)]
+pub struct CacheSimulation<F: FeedCapacity = LargestCapacity> {
+    clock: F::Clock,
+    last_accesses: F::FeedClocks,
+
+    /// State of the optional cache model and simulation features, allocated
+    /// the first time that one of them needs it, so that plain LRU
+    /// simulations only pay for a null pointer
+    extensions: Option<Box<SimulationExtensions<F::Clock>>>,
+}
+
+//
+impl<F: FeedCapacity> CacheSimulation<F> {
+    /// Maximal number of entry accesses in a simulation, beyond which its
+    /// clock would overflow
+    pub const MAX_ACCESSES: usize = F::Clock::MAX - 1;
+
+    /// Set up some cache entries and a clock
+    fn new(model: &CacheModel) -> Self {
+        if let Err(unsupported) = model.check_features() {
+            panic!("Unsupported cache model: {}", unsupported);
+        }
+        assert!(
+            model.num_feeds() <= F::MAX_FEEDS,
+            "Simulation cannot track all the feeds of the cache model"
+        );
+        let mut sim = Self {
+            clock: F::Clock::from_usize(1),
+            last_accesses: F::FeedClocks::new(model.num_feeds()),
+            extensions: None,
+        };
+        if model.simulates_sets() {
+            sim.extensions_mut().cache_sets = Some(CacheSetsState::new(model));
+        }
+        if model.cached_outputs().is_some() {
+            sim.extensions_mut().output_footprints = Some(OutputFootprints::default());
+        }
+        sim
+    }
+
+    /// Access the state of the optional features, if any is in use
+    fn extensions(&self) -> Option<&SimulationExtensions<F::Clock>> {
+        self.extensions.as_deref()
+    }
+
+    /// Access the state of the optional features, allocating it if need be
+    fn extensions_mut(&mut self) -> &mut SimulationExtensions<F::Clock> {
+        self.extensions.get_or_insert_with(Box::default)
+    }
+
+    /// Access the recency index, if enabled
+    fn recency(&self) -> Option<&RecencyIndex> {
+        self.extensions()?.recency.as_ref()
+    }
+
+    /// Index entry access times, so that finding how many entries, bytes and
+    /// set-associative cache lines were accessed since a given entry takes
+    /// O(log n) time instead of O(n)
+    ///
+    /// This makes the simulation state bigger and more expensive to clone, so
+    /// it is only worthwhile when simulating many feeds, and not in the brute
+    /// force search, which clones simulations at every step of every path. It
+    /// must be enabled before the first access.
+    ///
+    /// Set-associative cache lines are only indexed if this takes less than a
+    /// few million values, otherwise finding them still takes O(n) time. So do
+    /// TLB misses, whose pages are shared between neighboring feeds.
+    ///
+    pub fn enable_recency_index(&mut self, model: &CacheModel) {
+        assert_eq!(
+            self.num_accessed_entries(),
+            0,
+            "Recency index must be enabled before the first access"
+        );
+        let mut weights = model.accessed_since_weights();
+        let num_entries = model.num_feeds() as usize;
+        if 2 * num_entries * (weights.len() + 1) > MAX_RECENCY_INDEX_VALUES {
+            weights.truncate(FIRST_SET_GROUP_WEIGHT);
+        }
+        self.extensions_mut().recency = Some(RecencyIndex::new(num_entries, &weights));
+    }
+
+    /// Start keeping access statistics, see `stats()`
+    pub fn enable_stats(&mut self, model: &CacheModel) {
+        self.extensions_mut().stats = Some(CacheStats::new(model.levels.len()));
+    }
+
+    /// Access statistics, if enabled by `enable_stats()`
+    pub fn stats(&self) -> Option<&CacheStats> {
+        self.extensions()?.stats.as_ref()
+    }
+
+    /// Check out how many other entries have been accessed since a cache entry
+    /// was last accessed, return 0 if the entry was never accessed.
+    fn age(&self, entry: Entry) -> Option<usize> {
+        if let Some(recency) = self.recency() {
+            return recency.age(entry);
+        }
+        let last_access_time = self.last_access(entry);
+        if last_access_time == 0 {
+            None
+        } else {
+            Some(
+                self.last_access_times()
+                    .filter(|&access_time| access_time > last_access_time)
+                    .count(),
+            )
+        }
+    }
+
+    /// Enumerate the entries that were accessed since an entry was last
+    /// accessed (or all accessed entries if that entry was never accessed)
+    fn accessed_since(&self, entry: Entry) -> impl Iterator<Item = Entry> + Clone + '_ {
+        let last_access_time = self.last_access(entry);
+        self.last_access_times()
+            .enumerate()
+            .filter(move |&(_entry, access_time)| access_time > last_access_time)
+            .map(|(entry, _access_time)| entry as Entry)
+    }
+
+    /// Sum some per-entry weight over the entries that were accessed since an
+    /// entry was last accessed (or over all accessed entries if that entry was
+    /// never accessed), given the index of this weight in
+    /// `CacheModel::accessed_since_weights()` and its value for each entry
+    fn weight_accessed_since(
+        &self,
+        entry: Entry,
+        weight_idx: usize,
+        weight: impl Fn(Entry) -> usize,
+    ) -> usize {
+        self.recency()
+            .and_then(|recency| recency.weight_since(entry, weight_idx))
+            .unwrap_or_else(|| self.accessed_since(entry).map(weight).sum())
+    }
+
+    /// Time at which an entry was last accessed, or 0 if it was never accessed
+    fn last_access(&self, entry: Entry) -> usize {
+        self.last_accesses.as_ref()[entry as usize].to_usize()
+    }
+
+    /// Time at which each entry was last accessed, or 0 if it was never
+    /// accessed
+    fn last_access_times(&self) -> impl Iterator<Item = usize> + Clone + '_ {
+        self.last_accesses
+            .as_ref()
+            .iter()
+            .map(|&access_time| access_time.to_usize())
+    }
+
+    /// Record that an entry is accessed at the current time, and move on to
+    /// the next clock tick
+    fn record_access_time(&mut self, model: &CacheModel, entry: Entry) {
+        if let Some(extensions) = &mut self.extensions {
+            if let Some(footprints) = &mut extensions.output_footprints {
+                let last_access_time = self.last_accesses.as_ref()[entry as usize].to_usize();
+                footprints.record_feed_access(
+                    model,
+                    last_access_time,
+                    model.layout.entry_size(entry),
+                );
+            }
+            if let Some(recency) = &mut extensions.recency {
+                recency.record_access(entry);
+            }
+        }
+        self.last_accesses.as_mut()[entry as usize] = self.clock;
+        self.clock.increment();
+    }
+
+    /// Record that an output entry is accessed at the current time, and move
+    /// on to the next clock tick
+    fn record_output_access(&mut self, model: &CacheModel) {
+        if let (Some(footprints), Some(outputs)) = (
+            self.extensions
+                .as_mut()
+                .and_then(|extensions| extensions.output_footprints.as_mut()),
+            model.cached_outputs(),
+        ) {
+            footprints.record_output_access(model, self.clock.to_usize(), outputs.size);
+        }
+        self.clock.increment();
+    }
+
+    /// Tell how many bytes of feed and output entries were accessed since an
+    /// entry was last accessed, including that entry, if it was accessed
+    fn reuse_bytes(&self, model: &CacheModel, entry: Entry) -> Option<usize> {
+        let age = self.age(entry)?;
+        let layout = &model.layout;
+        let feed_bytes = if layout.is_uniform() {
+            (age + 1) * layout.entry_size(entry)
+        } else {
+            layout.entry_size(entry)
+                + self.weight_accessed_since(entry, ENTRY_SIZE_WEIGHT, |other| {
+                    layout.entry_size(other)
+                })
+        };
+        let output_bytes = model.cached_outputs().map_or(0, |outputs| {
+            self.outputs_since(self.last_access(entry)) * outputs.size
+        });
+        Some(feed_bytes + output_bytes)
+    }
+
+    /// Count the output entries that were accessed after a certain time
+    ///
+    /// When output entries are modeled, they are accessed on every third
+    /// clock tick, after the two feed entries of each pair.
+    ///
+    fn outputs_since(&self, access_time: usize) -> usize {
+        (self.clock.to_usize() - 1) / 3 - access_time / 3
+    }
+
+    /// Compute the cost of writing back the output entries that are evicted
+    /// from some cache levels by an upcoming access, given how many more bytes
+    /// this access puts on top of each output entry (as a function of the
+    /// output entry's access time)
+    fn writeback_cost(
+        &self,
+        model: &CacheModel,
+        levels: Range<usize>,
+        added_bytes: impl Fn(usize) -> usize,
+    ) -> Result<Cost, CostOverflow> {
+        let (outputs, footprints) = match (
+            model.cached_outputs(),
+            self.extensions()
+                .and_then(|extensions| extensions.output_footprints.as_ref()),
+        ) {
+            (Some(outputs), Some(footprints)) => (outputs, footprints),
+            _ => return Ok(Cost::zero()),
+        };
+        let mut cost = Cost::zero();
+        for footprint in footprints.0.iter() {
+            let added_bytes = added_bytes(footprint.access_time);
+            if added_bytes == 0 {
+                continue;
+            }
+            let old_footprint = footprint.bytes;
+            let new_footprint = old_footprint + added_bytes;
+            for (capacity, &writeback_cost) in model
+                .effective_capacities()
+                .zip(outputs.writeback_costs.iter())
+                .take(levels.end)
+                .skip(levels.start)
+            {
+                if old_footprint <= capacity && new_footprint > capacity {
+                    cost.add_cost(writeback_cost)?;
+                }
+            }
+        }
+        Ok(cost)
+    }
+
+    /// Compute the cost of writing back the output entries that are evicted
+    /// from some cache levels by an upcoming access to a feed entry
+    fn feed_writeback_cost(
+        &self,
+        model: &CacheModel,
+        levels: Range<usize>,
+        entry: Entry,
+    ) -> Result<Cost, CostOverflow> {
+        let last_access_time = self.last_access(entry);
+        self.writeback_cost(model, levels, |output_access_time| {
+            if last_access_time < output_access_time {
+                model.layout.entry_size(entry)
+            } else {
+                0
+            }
+        })
+    }
+
+    /// Simulate a cache access and return its cost
+    ///
+    /// If the cost of the access is out of Cost range, an error is returned,
+    /// but the simulation still accounts for the access.
+    ///
+    pub fn simulate_access(
+        &mut self,
+        model: &CacheModel,
+        entry: Entry,
+    ) -> Result<AccessCost, CostOverflow> {
+        self.simulate_access_impl(model, entry, None)
+    }
+
+    /// Simulate a cache access by one of several threads, which share the
+    /// cache levels starting at `first_shared_level`, and return its cost
+    ///
+    /// `self` tracks the accesses of this thread, which are all that its
+    /// private cache levels see, whereas `shared` tracks the accesses of all
+    /// threads, which compete for the capacity of the shared cache levels.
+    ///
+    pub(super) fn simulate_shared_access(
+        &mut self,
+        model: &CacheModel,
+        entry: Entry,
+        shared: &mut Self,
+        first_shared_level: usize,
+    ) -> Result<AccessCost, CostOverflow> {
+        self.simulate_access_impl(model, entry, Some((shared, first_shared_level)))
+    }
+
+    /// Common part of simulate_access and simulate_shared_access
+    fn simulate_access_impl(
+        &mut self,
+        model: &CacheModel,
+        entry: Entry,
+        shared: Option<(&mut Self, usize)>,
+    ) -> Result<AccessCost, CostOverflow> {
+        let mut first_access = self.last_access(entry) == 0;
+        // Reuse distances are only needed by latency curves, and slow to compute
+        let needs_reuse_bytes = model.latency_curve.is_some();
+        let mut reuse_bytes = if needs_reuse_bytes {
+            self.reuse_bytes(model, entry)
+        } else {
+            None
+        };
+        let cache_sets = self
+            .extensions
+            .as_mut()
+            .and_then(|extensions| extensions.cache_sets.as_mut());
+        let mut hit_level = if let Some(cache_sets) = cache_sets {
+            cache_sets.access(model, entry)
+        } else if first_access {
+            None
+        } else {
+            model.stack_hit_level(self, entry)
+        };
+
+        // Accesses that miss the private cache levels may hit the shared ones,
+        // whose contents depend on what other threads accessed
+        let mut private_levels = 0..model.levels.len();
+        let mut shared_write_cost = Ok(Cost::zero());
+        if let Some((shared, first_shared_level)) = shared {
+            debug_assert!(shared
+                .extensions()
+                .and_then(|extensions| extensions.cache_sets.as_ref())
+                .is_none());
+            private_levels.end = first_shared_level;
+            first_access = shared.last_access(entry) == 0;
+            if matches!(hit_level, Some(level) if level < first_shared_level) {
+                // An inclusive shared level invalidates the private copies of
+                // the entries that it evicts. We cannot tell whether another
+                // thread brought the entry back since then, so we only check
+                // that the shared level still holds it.
+                if model.inclusion == Inclusion::Inclusive
+                    && model.stack_hit_level(shared, entry).is_none()
+                {
+                    hit_level = None;
+                    if needs_reuse_bytes {
+                        reuse_bytes = shared.reuse_bytes(model, entry);
+                    }
+                }
+            } else {
+                hit_level = if first_access {
+                    None
+                } else {
+                    model
+                        .stack_hit_level(shared, entry)
+                        .map(|level| level.max(first_shared_level))
+                };
+                if needs_reuse_bytes {
+                    reuse_bytes = shared.reuse_bytes(model, entry);
+                }
+            }
+            shared_write_cost =
+                shared.feed_writeback_cost(model, first_shared_level..model.levels.len(), entry);
+            shared.record_access_time(model, entry);
+        }
+
+        if let Some(stats) = self
+            .extensions
+            .as_mut()
+            .and_then(|extensions| extensions.stats.as_mut())
+        {
+            stats.record_access(entry, first_access, hit_level);
+        }
+
+        let prefetched = match &model.prefetcher {
+            Some(prefetcher) if hit_level != Some(0) => self
+                .extensions_mut()
+                .prefetch_streams
+                .observe(prefetcher, entry),
+            _ => false,
+        };
+        let latency = model
+            .miss_level(hit_level)
+            .map_or(Cost::zero(), |miss_level| {
+                model.miss_latency(miss_level, reuse_bytes)
+            });
+        let hidden_latency = match &model.memory_parallelism {
+            _ if first_access || hit_level == Some(0) => Cost::zero(),
+            _ if prefetched => latency,
+            Some(memory_parallelism) => {
+                let clock = self.clock;
+                self.extensions_mut()
+                    .miss_group
+                    .observe(memory_parallelism, model.ticks_per_pair(), clock, latency)
+                    .unwrap_or_else(Cost::zero)
+            }
+            None => Cost::zero(),
+        };
+        let read_cost = model
+            .cost_model(entry, first_access, hit_level, latency, hidden_latency)
+            .and_then(|mut read_cost| {
+                if let Some(tlb) = &model.tlb {
+                    read_cost.add_cost(tlb.access_cost(self.last_accesses.as_ref(), entry)?)?;
+                }
+                Ok(read_cost)
+            });
+        let write_cost = self
+            .feed_writeback_cost(model, private_levels, entry)
+            .and_then(|mut write_cost| {
+                write_cost.add_cost(shared_write_cost?)?;
+                Ok(write_cost)
+            });
+
+        // Record the access before reporting any overflow, so that the
+        // simulation remains consistent
+        self.record_access_time(model, entry);
+        Ok(AccessCost {
+            read: read_cost?,
+            write: write_cost?,
+        })
+    }
+
+    /// Simulate an access to the output entry of the pair whose feed entries
+    /// were just accessed, and return its cost (zero if output entries are
+    /// not modeled, see `CacheModel::with_output_entries()`)
+    ///
+    /// As with `simulate_access()`, the access is accounted for even if its
+    /// cost is out of Cost range.
+    ///
+    pub fn simulate_output_access(
+        &mut self,
+        model: &CacheModel,
+    ) -> Result<AccessCost, CostOverflow> {
+        self.simulate_output_access_impl(model, None)
+    }
+
+    /// Multi-threaded version of simulate_output_access, see
+    /// simulate_shared_access for the meaning of the extra parameters
+    pub(super) fn simulate_shared_output_access(
+        &mut self,
+        model: &CacheModel,
+        shared: &mut Self,
+        first_shared_level: usize,
+    ) -> Result<AccessCost, CostOverflow> {
+        self.simulate_output_access_impl(model, Some((shared, first_shared_level)))
+    }
+
+    /// Common part of simulate_output_access and simulate_shared_output_access
+    fn simulate_output_access_impl(
+        &mut self,
+        model: &CacheModel,
+        shared: Option<(&mut Self, usize)>,
+    ) -> Result<AccessCost, CostOverflow> {
+        let outputs = if let Some(outputs) = &model.outputs {
+            outputs
+        } else {
+            return Ok(AccessCost::default());
+        };
+        debug_assert_eq!(
+            self.clock.to_usize() % 3,
+            0,
+            "Output accesses should follow the two feed accesses of each pair"
+        );
+        let num_levels = model.levels.len();
+        let write = match model.output_stores {
+            OutputStores::WriteAllocate => {
+                let added_bytes = |_output_access_time| outputs.size;
+                if let Some((shared, first_shared_level)) = shared {
+                    let private_write =
+                        self.writeback_cost(model, 0..first_shared_level, added_bytes);
+                    let shared_write =
+                        shared.writeback_cost(model, first_shared_level..num_levels, added_bytes);
+                    shared.record_output_access(model);
+                    private_write.and_then(|mut write| {
+                        write.add_cost(shared_write?)?;
+                        Ok(write)
+                    })
+                } else {
+                    self.writeback_cost(model, 0..num_levels, added_bytes)
+                }
+            }
+            OutputStores::NonTemporal => {
+                if let Some((shared, _first_shared_level)) = shared {
+                    shared.record_output_access(model);
+                }
+                Ok(outputs.streaming_store_cost)
+            }
+        };
+        self.record_output_access(model);
+
+        // Each output entry is accessed only once, so reading it costs the same
+        // memory access whatever the iteration order. This constant is left
+        // out of the cost, which only reflects what the iteration order changes.
+        Ok(AccessCost {
+            read: Cost::zero(),
+            write: write?,
+        })
+    }
+
+    /// Count the number of cache entries that were accessed so far
+    pub fn num_accessed_entries(&self) -> usize {
+        if let Some(recency) = self.recency() {
+            return recency.num_accessed();
+        }
+        self.last_access_times()
+            .filter(|&access_time| access_time != 0)
+            .count()
+    }
+}
+
+/// Set of cache entries, where bit N is set if entry N is in the set
+#[derive(Clone, Copy, Default)]
+struct EntryBitmap([u64; Self::NUM_WORDS]);
+//
+impl EntryBitmap {
+    /// Number of 64-bit words needed to hold one bit per possible entry
+    const NUM_WORDS: usize = div_round_up(LargestCapacity::MAX_FEEDS as usize + 1, 64);
+
+    /// Add an entry to the set
+    fn insert(&mut self, entry: Entry) {
+        self.0[entry as usize / 64] |= 1 << (entry % 64);
+    }
+
+    /// Tell whether an entry is in the set
+    fn contains(&self, entry: Entry) -> bool {
+        self.0[entry as usize / 64] & (1 << (entry % 64)) != 0
+    }
+}
+
+/// Simulation state of the optional cache model and simulation features
+#[derive(Clone, Default)]
+struct SimulationExtensions<Clock: CompactUint> {
+    /// Access streams tracked by the prefetcher, if enabled
+    prefetch_streams: PrefetchStreams,
+
+    /// Group of overlapping cache misses, if memory-level parallelism is
+    /// modeled
+    miss_group: MissGroup<Clock>,
+
+    /// Contents of the simulated cache sets, if they cannot be deduced from
+    /// the order of previous accesses
+    cache_sets: Option<CacheSetsState>,
+
+    /// Footprints of the output entries that may still be written back, if
+    /// output entries are allocated into the cache
+    output_footprints: Option<OutputFootprints>,
+
+    /// Access statistics, if enabled
+    stats: Option<CacheStats>,
+
+    /// Index of entry access times, if enabled
+    recency: Option<RecencyIndex>,
+}
+
+/// Contents of the cache sets which are explicitly simulated
+#[derive(Clone)]
+struct CacheSetsState {
+    /// Cache line held by each way, if any, identified by the entry that it
+    /// belongs to and its index among that entry's lines in this cache set
+    lines: Box<[Option<(Entry, usize)>]>,
+
+    /// Per-way replacement policy metadata
+    way_metadata: Box<[u8]>,
+
+    /// Per-set replacement policy metadata
+    set_metadata: Box<[u64]>,
+}
+//
+impl CacheSetsState {
+    /// Set up empty cache sets
+    fn new(model: &CacheModel) -> Self {
+        let all_sets = || model.simulated_sets.iter().flat_map(|sets| sets.iter());
+        let num_ways = all_sets().map(|set| set.ways).sum();
+        Self {
+            lines: vec![None; num_ways].into_boxed_slice(),
+            way_metadata: vec![0; num_ways].into_boxed_slice(),
+            set_metadata: all_sets()
+                .map(|set| model.replacement_policy.initial_set_metadata(set.index))
+                .collect(),
+        }
+    }
+
+    /// Simulate an access to every cache line of an entry, return the first
+    /// cache level that held all of these lines (None if only memory did)
+    fn access(&mut self, model: &CacheModel, entry: Entry) -> Option<usize> {
+        let num_levels = model.simulated_sets.len();
+        match model.inclusion {
+            // Every level sees every access
+            Inclusion::Inclusive => {
+                let mut hit_level = None;
+                for level in 0..num_levels {
+                    let level_hit =
+                        self.access_level(model, level, entry, &mut EntryBitmap::default());
+                    if level_hit && hit_level.is_none() {
+                        hit_level = Some(level);
+                    }
+                }
+                hit_level
+            }
+
+            // Levels only see the accesses that missed the levels above them
+            Inclusion::NonInclusive => (0..num_levels)
+                .find(|&level| self.access_level(model, level, entry, &mut EntryBitmap::default())),
+
+            // The entry moves from the level where it was found to the first
+            // level, and entries evicted from each level fall to the next one.
+            // Entries that are only partially evicted are duplicated, which is
+            // a good enough approximation of real victim caches.
+            Inclusion::Exclusive => {
+                let hit_level =
+                    (0..num_levels).find(|&level| self.level_holds(model, level, entry));
+                if let Some(level) = hit_level.filter(|&level| level > 0) {
+                    self.remove(model, level, entry);
+                }
+                let mut victims = EntryBitmap::default();
+                self.access_level(model, 0, entry, &mut victims);
+                for level in 1..num_levels {
+                    let mut next_victims = EntryBitmap::default();
+                    for victim in (0..model.num_feeds()).filter(|&feed| victims.contains(feed)) {
+                        self.access_level(model, level, victim, &mut next_victims);
+                    }
+                    victims = next_victims;
+                }
+                hit_level
+            }
+        }
+    }
+
+    /// Simulate an access to the cache lines of an entry within one cache
+    /// level, tell whether all of these lines were present in that level and
+    /// record the entries whose lines were evicted in a bitmap
+    fn access_level(
+        &mut self,
+        model: &CacheModel,
+        level: usize,
+        entry: Entry,
+        victims: &mut EntryBitmap,
+    ) -> bool {
+        let mut level_hit = true;
+        for set in model.simulated_sets[level].iter() {
+            level_hit &= self.access_set(&*model.replacement_policy, set, entry, victims);
+        }
+        level_hit
+    }
+
+    /// Tell whether all the cache lines of an entry are present in a level
+    fn level_holds(&self, model: &CacheModel, level: usize, entry: Entry) -> bool {
+        model.simulated_sets[level].iter().all(|set| {
+            let lines = &self.lines[set.first_way..set.first_way + set.ways];
+            (0..set.lines_per_feed[entry as usize])
+                .all(|line_idx| lines.contains(&Some((entry, line_idx))))
+        })
+    }
+
+    /// Remove the cache lines of an entry from a level
+    fn remove(&mut self, model: &CacheModel, level: usize, entry: Entry) {
+        for set in model.simulated_sets[level].iter() {
+            for line in self.lines[set.first_way..set.first_way + set.ways].iter_mut() {
+                if matches!(line, Some((line_entry, _)) if *line_entry == entry) {
+                    *line = None;
+                }
+            }
+        }
+    }
+
+    /// Simulate an access to the cache lines of an entry within one cache set,
+    /// tell whether all of these lines were present in the set
+    fn access_set(
+        &mut self,
+        policy: &dyn ReplacementPolicy,
+        set: &SimulatedSet,
+        entry: Entry,
+        victims: &mut EntryBitmap,
+    ) -> bool {
+        let num_lines = set.lines_per_feed[entry as usize];
+        if set.ways == 0 {
+            return num_lines == 0;
+        }
+        let ways = set.first_way..set.first_way + set.ways;
+        let mut hit = true;
+        for line_idx in 0..num_lines {
+            let line = Some((entry, line_idx));
+            let lines = &mut self.lines[ways.clone()];
+            let way_metadata = &mut self.way_metadata[ways.clone()];
+            let set_metadata = &mut self.set_metadata[set.index];
+            let (way, inserted) = if let Some(way) = lines.iter().position(|&l| l == line) {
+                (way, false)
+            } else {
+                hit = false;
+                let way = lines.iter().position(Option::is_none).unwrap_or_else(|| {
+                    policy.victim(CacheSetState {
+                        way_metadata: &mut *way_metadata,
+                        set_metadata: &mut *set_metadata,
+                    })
+                });
+                if let Some((victim, _line_idx)) = lines[way] {
+                    victims.insert(victim);
+                }
+                lines[way] = line;
+                (way, true)
+            };
+            policy.on_access(
+                CacheSetState {
+                    way_metadata,
+                    set_metadata,
+                },
+                way,
+                inserted,
+            );
+        }
+        hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recency_index_matches_scans() {
+        // Two set-associative levels, with feeds of two different sizes
+        let level = |capacity, miss_cost, sets| CacheLevel {
+            capacity,
+            miss_cost: Cost::from_num(miss_cost),
+            line_transfer_cost: 0.0,
+            associativity: Some(SetAssociativity {
+                ways: capacity / (64 * sets),
+                sets,
+                line_size: 64,
+            }),
+        };
+        let hierarchy =
+            CacheHierarchy::new("test", vec![level(2048, 1, 4), level(8192, 10, 8)]).unwrap();
+        let layout = FeedLayout::new(24, 192, 256).with_second_set(16, 320);
+        for model in [
+            CacheModel::new(&hierarchy, layout.clone()),
+            CacheModel::new_set_associative(&hierarchy, layout),
+        ] {
+            let mut scanning_sim = model.start_simulation::<LargestCapacity>();
+            let mut indexed_sim = model.start_simulation::<LargestCapacity>();
+            indexed_sim.enable_recency_index(&model);
+            let mut state = 42u32;
+            // Enough accesses for the index to relabel access times
+            for _ in 0..500 {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let accessed = ((state >> 16) % 24) as Entry;
+                assert_eq!(
+                    indexed_sim.simulate_access(&model, accessed),
+                    scanning_sim.simulate_access(&model, accessed)
+                );
+                for entry in 0..24 {
+                    assert_eq!(indexed_sim.age(entry), scanning_sim.age(entry));
+                    assert_eq!(
+                        indexed_sim.reuse_bytes(&model, entry),
+                        scanning_sim.reuse_bytes(&model, entry)
+                    );
+                    if scanning_sim.age(entry).is_some() {
+                        assert_eq!(
+                            model.stack_hit_level(&indexed_sim, entry),
+                            model.stack_hit_level(&scanning_sim, entry)
+                        );
+                    }
+                }
+                assert_eq!(
+                    indexed_sim.num_accessed_entries(),
+                    scanning_sim.num_accessed_entries()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn models_presets_and_files() {
+        let layout = FeedLayout::new(4, 1024, 1024);
+        let preset = CacheModel::from_preset("zen3", layout.clone()).unwrap();
+        assert_eq!(
+            preset.levels[..],
+            CacheHierarchy::from_preset("Zen3").unwrap().levels[..]
+        );
+        assert!(matches!(
+            CacheModel::from_preset("Pentium", layout.clone()),
+            Err(HierarchyError::UnknownPreset(_))
+        ));
+
+        let path = std::env::temp_dir().join(format!("cachot-model-{}.toml", std::process::id()));
+        std::fs::write(&path, "[[level]]\ncapacity = 0\nmiss_cost = 1\n").unwrap();
+        let invalid = CacheModel::from_file(&path, layout);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            invalid,
+            Err(HierarchyError::ZeroCapacity { level: 0 })
+        ));
+    }
+}
@@ -0,0 +1,124 @@
+//! Simulation of several threads whose cores share the last cache level
+
+use super::{AccessCost, CacheModel, CacheSimulation, CostOverflow, Entry, UnsupportedFeature};
+
+/// Model of several CPU cores, each of which runs one thread
+///
+/// Every core has its own copy of the private cache levels of the underlying
+/// CacheModel, that is all levels but the last one, which is shared by all
+/// cores. Feed entries are shared between threads, so an entry that a thread
+/// brought into the shared level may be reused by another thread, but threads
+/// also compete for the capacity of the shared level.
+///
+/// This is only supported when the contents of cache sets can be deduced from
+/// the order of previous accesses.
+///
+/// In inclusive hierarchies, evicting an entry from the shared level also
+/// invalidates its copies in the private levels of every core. Private hits
+/// are thus turned into misses when the shared level does not hold the entry
+/// anymore. An entry that was evicted from the shared level, then brought back
+/// by another thread, is still counted as a private hit, which makes this
+/// model slightly optimistic.
+///
+#[derive(Clone, Debug)]
+pub struct MultiCoreModel {
+    /// Cache model of a single core
+    model: CacheModel,
+
+    /// Number of threads
+    num_threads: usize,
+}
+//
+impl MultiCoreModel {
+    /// Set up a multi-core model with a certain number of threads
+    pub fn new(model: CacheModel, num_threads: usize) -> Result<Self, UnsupportedFeature> {
+        assert!(num_threads > 0, "There should be at least one thread");
+        if num_threads > 1 && model.simulates_sets() {
+            return Err(UnsupportedFeature::MultiCore);
+        }
+        Ok(Self { model, num_threads })
+    }
+
+    /// Query the number of threads
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    /// Index of the first cache level that is shared by all cores
+    fn first_shared_level(&self) -> usize {
+        self.model.levels.len() - 1
+    }
+
+    /// Start a multi-core simulation
+    pub fn start_simulation(&self) -> MultiCoreSimulation {
+        MultiCoreSimulation {
+            threads: (0..self.num_threads)
+                .map(|_thread| self.model.start_simulation())
+                .collect(),
+            shared: self.model.start_simulation(),
+        }
+    }
+}
+
+/// Multi-core cache simulation
+///
+/// Threads are assumed to progress at the same rate, so their accesses should
+/// be simulated in a round-robin fashion, one feed pair at a time.
+///
+#[derive(Clone)]
+pub struct MultiCoreSimulation {
+    /// Accesses of each thread, as seen by its private cache levels
+    threads: Box<[CacheSimulation]>,
+
+    /// Accesses of all threads, as seen by the shared cache level
+    shared: CacheSimulation,
+}
+//
+impl MultiCoreSimulation {
+    /// Index the access times of every thread's simulation and of the
+    /// shared one, see `CacheSimulation::enable_recency_index()`
+    pub fn enable_recency_index(&mut self, model: &MultiCoreModel) {
+        for sim in self
+            .threads
+            .iter_mut()
+            .chain(std::iter::once(&mut self.shared))
+        {
+            sim.enable_recency_index(&model.model);
+        }
+    }
+
+    /// Simulate a cache access by some thread and return its cost
+    pub fn simulate_access(
+        &mut self,
+        model: &MultiCoreModel,
+        thread: usize,
+        entry: Entry,
+    ) -> Result<AccessCost, CostOverflow> {
+        if model.num_threads == 1 {
+            return self.threads[0].simulate_access(&model.model, entry);
+        }
+        self.threads[thread].simulate_shared_access(
+            &model.model,
+            entry,
+            &mut self.shared,
+            model.first_shared_level(),
+        )
+    }
+
+    /// Simulate an access to the output entry of the pair whose feed entries
+    /// were just accessed by some thread, and return its cost
+    pub fn simulate_output_access(
+        &mut self,
+        model: &MultiCoreModel,
+        thread: usize,
+    ) -> Result<AccessCost, CostOverflow> {
+        if model.num_threads == 1 {
+            return self.threads[0].simulate_output_access(&model.model);
+        }
+        self.threads[thread].simulate_shared_output_access(
+            &model.model,
+            &mut self.shared,
+            model.first_shared_level(),
+        )
+    }
+}
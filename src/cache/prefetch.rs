@@ -0,0 +1,108 @@
+//! Hardware prefetcher model
+
+use super::Entry;
+
+/// Stride prefetcher configuration
+///
+/// Feed buffers are laid out at a regular stride in memory, so accessing feed
+/// entries whose indices form an arithmetic progression generates a memory
+/// access stream that hardware stride prefetchers can follow.
+///
+/// The prefetcher is trained on the accesses that miss the L1 cache, and when
+/// it correctly predicts the next such access, the latency of that cache miss
+/// is hidden so that only the cost of transferring the data remains. We do not
+/// model the cache pollution caused by incorrect predictions.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Prefetcher {
+    /// Largest distance between successive missed entries (in entries) that
+    /// the prefetcher recognizes as a stride
+    pub max_stride: Entry,
+}
+//
+impl Default for Prefetcher {
+    fn default() -> Self {
+        Self { max_stride: 2 }
+    }
+}
+
+/// Number of access streams that the prefetcher tracks simultaneously
+const NUM_STREAMS: usize = 2;
+
+/// Access streams tracked by the prefetcher, most recently used first
+#[derive(Clone, Copy, Default)]
+pub(super) struct PrefetchStreams([Option<Stream>; NUM_STREAMS]);
+//
+impl PrefetchStreams {
+    /// Observe an access that missed the L1 cache, tell whether it was
+    /// correctly predicted by the prefetcher
+    pub fn observe(&mut self, config: &Prefetcher, entry: Entry) -> bool {
+        // Check if an established stream predicted this access
+        let predicting_stream = self
+            .0
+            .iter()
+            .position(|stream| matches!(stream, Some(stream) if stream.predicts(entry)));
+        if let Some(idx) = predicting_stream {
+            self.touch(idx, entry, None);
+            return true;
+        }
+
+        // Otherwise, check if this access can train a stream's stride
+        let training_stream = self.0.iter().position(
+            |stream| matches!(stream, Some(stream) if stream.can_train(entry, config.max_stride)),
+        );
+        if let Some(idx) = training_stream {
+            let stride = self.0[idx].unwrap().delta(entry) as i8;
+            self.touch(idx, entry, Some(stride));
+            return false;
+        }
+
+        // If all else fails, start a new stream, replacing the least recently
+        // used one
+        self.0.rotate_right(1);
+        self.0[0] = Some(Stream {
+            last_entry: entry,
+            stride: 0,
+        });
+        false
+    }
+
+    /// Record an access to a stream, possibly updating its stride, and make it
+    /// the most recently used stream
+    fn touch(&mut self, idx: usize, entry: Entry, stride: Option<i8>) {
+        let stream = self.0[idx].as_mut().unwrap();
+        stream.last_entry = entry;
+        if let Some(stride) = stride {
+            stream.stride = stride;
+        }
+        self.0[..=idx].rotate_right(1);
+    }
+}
+
+/// Access stream tracked by the prefetcher
+#[derive(Clone, Copy)]
+struct Stream {
+    /// Last entry that was accessed by this stream
+    last_entry: Entry,
+
+    /// Distance between successive entries of this stream, or 0 if unknown
+    stride: i8,
+}
+//
+impl Stream {
+    /// Distance from the last entry of this stream to another entry
+    fn delta(&self, entry: Entry) -> isize {
+        entry as isize - self.last_entry as isize
+    }
+
+    /// Tell whether this stream predicted an access to some entry
+    fn predicts(&self, entry: Entry) -> bool {
+        self.stride != 0 && self.delta(entry) == self.stride as isize
+    }
+
+    /// Tell whether an access to some entry can train this stream's stride
+    fn can_train(&self, entry: Entry, max_stride: Entry) -> bool {
+        let delta = self.delta(entry);
+        delta != 0 && delta.unsigned_abs() <= max_stride as usize
+    }
+}
@@ -0,0 +1,224 @@
+//! Order statistics over the last access time of cache entries
+
+use super::Entry;
+
+/// Index of the last access time of every cache entry, which tells how many
+/// entries were accessed since another entry was last accessed, and sums
+/// per-entry weights over these entries, in O(log n)
+///
+/// The compact cache simulation state only records the last access time of
+/// each entry, so answering these questions requires scanning all of them,
+/// which is fine for the brute force search's handful of feeds but not for
+/// evaluating iteration schemes over hundreds of feeds. This index keeps a
+/// Fenwick tree over access times, in which the last access of each entry
+/// accounts for 1 and for the entry's weights.
+///
+/// Access times are private to the index. Whenever they run out, the last
+/// accesses of all entries are relabeled to consecutive times, which preserves
+/// their order. This way, the index takes O(n) memory (per weight) however
+/// many accesses are recorded, and relabeling costs O(log n) per access when
+/// amortized over the O(n) accesses between two relabelings.
+///
+#[derive(Clone, Debug)]
+pub(super) struct RecencyIndex {
+    /// Last access time of each entry, or 0 if it was never accessed
+    last_accesses: Box<[usize]>,
+
+    /// Weights of each entry, stored entry by entry
+    weights: Box<[usize]>,
+
+    /// Number of weights of each entry
+    num_weights: usize,
+
+    /// Fenwick tree over access times, storing for each time how many entries
+    /// were last accessed at that time, followed by the sum of each of their
+    /// weights. Times are 1-based, as in the tree's indexing scheme.
+    tree: Box<[usize]>,
+
+    /// Number of entries that were accessed so far
+    num_accessed: usize,
+
+    /// Time of the next access
+    clock: usize,
+}
+//
+impl RecencyIndex {
+    /// Set up an empty index for a certain number of entries, given the
+    /// per-entry weights that it should sum
+    pub fn new(num_entries: usize, weights: &[Box<[usize]>]) -> Self {
+        assert!(
+            weights.iter().all(|weight| weight.len() == num_entries),
+            "There should be one weight per entry"
+        );
+        let num_weights = weights.len();
+        let weights = (0..num_entries)
+            .flat_map(|entry| weights.iter().map(move |weight| weight[entry]))
+            .collect();
+        let num_times = 2 * num_entries.max(1);
+        Self {
+            last_accesses: vec![0; num_entries].into_boxed_slice(),
+            weights,
+            num_weights,
+            tree: vec![0; (num_times + 1) * (num_weights + 1)].into_boxed_slice(),
+            num_accessed: 0,
+            clock: 1,
+        }
+    }
+
+    /// Record an access to an entry
+    pub fn record_access(&mut self, entry: Entry) {
+        if self.clock * self.row_len() == self.tree.len() {
+            self.relabel();
+        }
+        let entry = entry as usize;
+        match self.last_accesses[entry] {
+            0 => self.num_accessed += 1,
+            last_access => self.update(last_access, entry, false),
+        }
+        self.update(self.clock, entry, true);
+        self.last_accesses[entry] = self.clock;
+        self.clock += 1;
+    }
+
+    /// Number of distinct entries that were accessed so far
+    pub fn num_accessed(&self) -> usize {
+        self.num_accessed
+    }
+
+    /// Number of other entries that were accessed since an entry was last
+    /// accessed, or None if it was never accessed
+    pub fn age(&self, entry: Entry) -> Option<usize> {
+        match self.last_accesses[entry as usize] {
+            0 => None,
+            last_access => Some(self.num_accessed - self.sum_until(last_access, 0)),
+        }
+    }
+
+    /// Sum of one of the weights over the entries that were accessed since an
+    /// entry was last accessed (or over all accessed entries if that entry was
+    /// never accessed), or None if this weight is not indexed
+    pub fn weight_since(&self, entry: Entry, weight: usize) -> Option<usize> {
+        if weight >= self.num_weights {
+            return None;
+        }
+        let column = weight + 1;
+        let last_access = self.last_accesses[entry as usize];
+        Some(self.sum_until(self.clock - 1, column) - self.sum_until(last_access, column))
+    }
+
+    /// Number of values stored per access time in the Fenwick tree
+    fn row_len(&self) -> usize {
+        self.num_weights + 1
+    }
+
+    /// Sum one column of the Fenwick tree over the access times that are at
+    /// or before some time
+    fn sum_until(&self, time: usize, column: usize) -> usize {
+        let mut idx = time;
+        let mut sum = 0;
+        while idx > 0 {
+            sum += self.tree[idx * self.row_len() + column];
+            idx &= idx - 1;
+        }
+        sum
+    }
+
+    /// Add or remove the contribution of an entry's last access
+    fn update(&mut self, time: usize, entry: usize, add: bool) {
+        let row_len = self.row_len();
+        let weights = &self.weights[entry * self.num_weights..(entry + 1) * self.num_weights];
+        let mut idx = time;
+        while idx * row_len < self.tree.len() {
+            let row = &mut self.tree[idx * row_len..(idx + 1) * row_len];
+            for (sum, &value) in row.iter_mut().zip(std::iter::once(&1).chain(weights)) {
+                if add {
+                    *sum += value;
+                } else {
+                    *sum -= value;
+                }
+            }
+            idx += idx & idx.wrapping_neg();
+        }
+    }
+
+    /// Relabel the last access of each entry to consecutive times, starting
+    /// from 1, and rebuild the Fenwick tree accordingly
+    fn relabel(&mut self) {
+        let mut accessed = (0..self.last_accesses.len())
+            .filter(|&entry| self.last_accesses[entry] != 0)
+            .collect::<Vec<_>>();
+        accessed.sort_unstable_by_key(|&entry| self.last_accesses[entry]);
+
+        // Put the contribution of each entry at its new access time...
+        let row_len = self.row_len();
+        self.tree.iter_mut().for_each(|sum| *sum = 0);
+        for (time, &entry) in (1..).zip(accessed.iter()) {
+            self.last_accesses[entry] = time;
+            let weights = &self.weights[entry * self.num_weights..(entry + 1) * self.num_weights];
+            let row = &mut self.tree[time * row_len..(time + 1) * row_len];
+            for (sum, &value) in row.iter_mut().zip(std::iter::once(&1).chain(weights)) {
+                *sum = value;
+            }
+        }
+        self.clock = accessed.len() + 1;
+
+        // ...then propagate it up the Fenwick tree, in linear time
+        let num_times = self.tree.len() / row_len;
+        for idx in 1..num_times {
+            let parent = idx + (idx & idx.wrapping_neg());
+            if parent < num_times {
+                for column in 0..row_len {
+                    self.tree[parent * row_len + column] += self.tree[idx * row_len + column];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_naive_scan() {
+        const NUM_ENTRIES: usize = 13;
+        let weights = [
+            (0..NUM_ENTRIES).map(|entry| 3 * entry + 1).collect(),
+            (0..NUM_ENTRIES).map(|entry| entry % 4).collect(),
+        ];
+        let mut index = RecencyIndex::new(NUM_ENTRIES, &weights);
+        let mut last_accesses = [0; NUM_ENTRIES];
+        let mut state = 42u32;
+        // Enough accesses for access times to be relabeled several times
+        for time in 1..=20 * NUM_ENTRIES {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let accessed = (state >> 16) as usize % NUM_ENTRIES;
+            index.record_access(accessed as Entry);
+            last_accesses[accessed] = time;
+
+            assert_eq!(
+                index.num_accessed(),
+                last_accesses.iter().filter(|&&time| time != 0).count()
+            );
+            for entry in 0..NUM_ENTRIES {
+                let last_access = last_accesses[entry];
+                let accessed_since = (0..NUM_ENTRIES)
+                    .filter(|&other| last_accesses[other] > last_access)
+                    .collect::<Vec<_>>();
+                let expected_age = if last_access == 0 {
+                    None
+                } else {
+                    Some(accessed_since.len())
+                };
+                assert_eq!(index.age(entry as Entry), expected_age);
+                for (idx, weight) in weights.iter().enumerate() {
+                    assert_eq!(
+                        index.weight_since(entry as Entry, idx),
+                        Some(accessed_since.iter().map(|&other| weight[other]).sum())
+                    );
+                }
+                assert_eq!(index.weight_since(entry as Entry, weights.len()), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Cache replacement policies
+
+use std::{fmt::Debug, rc::Rc};
+
+/// Replacement metadata of a cache set, as seen by a replacement policy
+pub struct CacheSetState<'sim> {
+    /// Per-way replacement metadata, one byte per cache line
+    pub way_metadata: &'sim mut [u8],
+
+    /// Set-wide replacement metadata
+    pub set_metadata: &'sim mut u64,
+}
+//
+impl CacheSetState<'_> {
+    /// Number of ways in this cache set
+    pub fn ways(&self) -> usize {
+        self.way_metadata.len()
+    }
+}
+
+/// Cache replacement policy
+///
+/// Decides which cache line of a full cache set gets evicted when a new cache
+/// line must be brought into that set.
+///
+pub trait ReplacementPolicy: Debug {
+    /// Name of the replacement policy, for reporting purposes
+    fn name(&self) -> &'static str;
+
+    /// Truth that this replacement policy is a stack algorithm
+    ///
+    /// With stack algorithms like LRU, the contents of a cache of any capacity
+    /// can be deduced from the order of past accesses, which is much cheaper
+    /// than simulating the contents of every cache set.
+    ///
+    fn is_stack_algorithm(&self) -> bool {
+        false
+    }
+
+    /// Initial set-wide replacement metadata of a cache set
+    fn initial_set_metadata(&self, _set_index: usize) -> u64 {
+        0
+    }
+
+    /// Record that the cache line at a certain way was accessed, either because
+    /// it was just brought into the cache (`inserted`) or on a cache hit
+    fn on_access(&self, set: CacheSetState, way: usize, inserted: bool);
+
+    /// Pick the way of a full cache set whose cache line should be evicted
+    fn victim(&self, set: CacheSetState) -> usize;
+}
+
+/// Least Recently Used replacement policy
+///
+/// Per-way metadata is the recency rank of each cache line, 0 being the most
+/// recently used line of the set.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lru;
+//
+impl ReplacementPolicy for Lru {
+    fn name(&self) -> &'static str {
+        "LRU"
+    }
+
+    fn is_stack_algorithm(&self) -> bool {
+        true
+    }
+
+    fn on_access(&self, set: CacheSetState, way: usize, inserted: bool) {
+        let old_rank = if inserted {
+            u8::MAX
+        } else {
+            set.way_metadata[way]
+        };
+        for rank in set.way_metadata.iter_mut() {
+            if *rank < old_rank {
+                *rank += 1;
+            }
+        }
+        set.way_metadata[way] = 0;
+    }
+
+    fn victim(&self, set: CacheSetState) -> usize {
+        (0..set.ways())
+            .max_by_key(|&way| set.way_metadata[way])
+            .expect("Cache sets should have at least one way")
+    }
+}
+
+/// First In First Out replacement policy
+///
+/// Since empty ways are filled in order, this is a round-robin over the ways,
+/// and set-wide metadata is the next way to be evicted.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fifo;
+//
+impl ReplacementPolicy for Fifo {
+    fn name(&self) -> &'static str {
+        "FIFO"
+    }
+
+    fn on_access(&self, _set: CacheSetState, _way: usize, _inserted: bool) {}
+
+    fn victim(&self, set: CacheSetState) -> usize {
+        let way = *set.set_metadata as usize % set.ways();
+        *set.set_metadata = (way + 1) as u64;
+        way
+    }
+}
+
+/// Random replacement policy
+///
+/// For reproducibility, each cache set uses its own pseudo-random number
+/// generator (xorshift64), whose state is the set-wide metadata.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Random {
+    /// Seed from which the state of each set's generator is derived
+    pub seed: u64,
+}
+//
+impl Default for Random {
+    fn default() -> Self {
+        Self {
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+//
+impl ReplacementPolicy for Random {
+    fn name(&self) -> &'static str {
+        "Random"
+    }
+
+    fn initial_set_metadata(&self, set_index: usize) -> u64 {
+        // SplitMix64 finalizer, so that sets get decorrelated nonzero states
+        let mut state = self
+            .seed
+            .wrapping_add((set_index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (state ^ (state >> 31)) | 1
+    }
+
+    fn on_access(&self, _set: CacheSetState, _way: usize, _inserted: bool) {}
+
+    fn victim(&self, set: CacheSetState) -> usize {
+        let mut state = *set.set_metadata;
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        *set.set_metadata = state;
+        (state % set.ways() as u64) as usize
+    }
+}
+
+/// Tree pseudo-LRU replacement policy
+///
+/// The ways are the leaves of a binary tree, whose nodes are stored in heap
+/// order (root at index 1, children of node N at 2N and 2N+1) as bits of the
+/// set-wide metadata. Each bit tells on which side of the node the victim
+/// should be searched, and accesses flip the bits on their path to point away
+/// from the accessed way.
+///
+/// Non-power-of-two way counts are handled by never descending into subtrees
+/// which only contain nonexistent ways.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TreePlru;
+//
+impl TreePlru {
+    /// Number of leaves of the binary tree, panics if there are too many ways
+    fn num_leaves(set: &CacheSetState) -> usize {
+        let num_leaves = set.ways().next_power_of_two();
+        assert!(
+            num_leaves <= 64,
+            "Tree-PLRU node bits must fit in the set-wide metadata"
+        );
+        num_leaves
+    }
+}
+//
+impl ReplacementPolicy for TreePlru {
+    fn name(&self) -> &'static str {
+        "Tree-PLRU"
+    }
+
+    fn on_access(&self, set: CacheSetState, way: usize, _inserted: bool) {
+        let mut node = Self::num_leaves(&set) + way;
+        while node > 1 {
+            let parent = node / 2;
+            let came_from_left = node & 1 == 0;
+            if came_from_left {
+                *set.set_metadata |= 1 << parent;
+            } else {
+                *set.set_metadata &= !(1 << parent);
+            }
+            node = parent;
+        }
+    }
+
+    fn victim(&self, set: CacheSetState) -> usize {
+        let num_leaves = Self::num_leaves(&set);
+        let mut node = 1;
+        while node < num_leaves {
+            let go_right = (*set.set_metadata >> node) & 1 != 0;
+            let mut child = 2 * node + go_right as usize;
+            let mut leftmost_leaf = child;
+            while leftmost_leaf < num_leaves {
+                leftmost_leaf *= 2;
+            }
+            if leftmost_leaf - num_leaves >= set.ways() {
+                child ^= 1;
+            }
+            node = child;
+        }
+        node - num_leaves
+    }
+}
+
+/// Static Re-Reference Interval Prediction replacement policy
+///
+/// Per-way metadata is the 2-bit re-reference prediction value (RRPV) of each
+/// cache line. New lines are predicted to be re-referenced in the distant
+/// future, lines that hit in the cache in the near-immediate future, and the
+/// victim is the first line predicted to be re-referenced the farthest away.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Srrip;
+//
+impl Srrip {
+    /// Maximal re-reference prediction value
+    const MAX_RRPV: u8 = 3;
+}
+//
+impl ReplacementPolicy for Srrip {
+    fn name(&self) -> &'static str {
+        "SRRIP"
+    }
+
+    fn on_access(&self, set: CacheSetState, way: usize, inserted: bool) {
+        set.way_metadata[way] = if inserted { Self::MAX_RRPV - 1 } else { 0 };
+    }
+
+    fn victim(&self, set: CacheSetState) -> usize {
+        loop {
+            if let Some(way) = set
+                .way_metadata
+                .iter()
+                .position(|&rrpv| rrpv >= Self::MAX_RRPV)
+            {
+                return way;
+            }
+            for rrpv in set.way_metadata.iter_mut() {
+                *rrpv += 1;
+            }
+        }
+    }
+}
+
+/// Look up a replacement policy by name (case-insensitive), using its default
+/// configuration
+pub fn from_name(name: &str) -> Option<Rc<dyn ReplacementPolicy>> {
+    let policies: [Rc<dyn ReplacementPolicy>; 5] = [
+        Rc::new(Lru),
+        Rc::new(Fifo),
+        Rc::new(Random::default()),
+        Rc::new(TreePlru),
+        Rc::new(Srrip),
+    ];
+    policies
+        .iter()
+        .find(|policy| policy.name().eq_ignore_ascii_case(name))
+        .cloned()
+}
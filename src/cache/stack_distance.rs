@@ -0,0 +1,66 @@
+//! Stack distance analysis of cache entry access streams
+
+use super::Entry;
+
+/// Histogram of the stack distances of a stream of cache entry accesses
+///
+/// The stack distance (or reuse distance) of an access is the number of
+/// distinct entries that were accessed since the previous access to the same
+/// entry. Following Mattson et al, an access hits a fully associative LRU cache
+/// of N entries if and only if its stack distance is smaller than N, so this
+/// histogram gives the number of misses for every cache capacity at once.
+///
+/// Accesses to entries that were never accessed before have an infinite stack
+/// distance, and are counted separately as cold accesses.
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StackDistanceHistogram {
+    /// Number of accesses with each stack distance
+    counts: Vec<usize>,
+
+    /// Number of accesses to entries that were never accessed before
+    cold_accesses: usize,
+}
+//
+impl StackDistanceHistogram {
+    /// Compute the stack distance histogram of a stream of entry accesses
+    pub fn new(accesses: impl IntoIterator<Item = Entry>) -> Self {
+        // LRU stack of entries, from most recently to least recently used
+        let mut lru_stack = Vec::<Entry>::new();
+        let mut histogram = Self::default();
+        for entry in accesses {
+            match lru_stack
+                .iter()
+                .position(|&stack_entry| stack_entry == entry)
+            {
+                Some(distance) => {
+                    if histogram.counts.len() <= distance {
+                        histogram.counts.resize(distance + 1, 0);
+                    }
+                    histogram.counts[distance] += 1;
+                    lru_stack.remove(distance);
+                }
+                None => histogram.cold_accesses += 1,
+            }
+            lru_stack.insert(0, entry);
+        }
+        histogram
+    }
+
+    /// Number of accesses with each stack distance, up to the largest
+    /// observed stack distance
+    pub fn counts(&self) -> &[usize] {
+        &self.counts[..]
+    }
+
+    /// Number of accesses to entries that were never accessed before
+    pub fn cold_accesses(&self) -> usize {
+        self.cold_accesses
+    }
+
+    /// Number of misses of a fully associative LRU cache that can hold a
+    /// certain number of entries, including cold misses
+    pub fn misses(&self, capacity: usize) -> usize {
+        self.cold_accesses + self.counts.iter().skip(capacity).sum::<usize>()
+    }
+}
@@ -0,0 +1,78 @@
+//! Cache access statistics
+
+use super::Entry;
+use std::fmt::{self, Display};
+
+/// Statistics about the feed accesses of a cache simulation
+///
+/// These tell where the cache cost of an iteration scheme comes from, e.g.
+/// whether it does better than another by avoiding L2 or L3 cache misses.
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CacheStats {
+    /// Number of accesses that hit each cache level
+    pub hits: Box<[usize]>,
+
+    /// Number of accesses that missed each cache level, excluding cold ones
+    pub misses: Box<[usize]>,
+
+    /// Number of accesses to feed entries that were never accessed before
+    pub cold_accesses: usize,
+
+    /// Number of times each feed entry was reloaded into the L1 cache after
+    /// its first access, up to the highest feed that was accessed
+    pub feed_reloads: Vec<usize>,
+}
+//
+impl CacheStats {
+    /// Set up statistics for a certain number of cache levels
+    pub(super) fn new(num_levels: usize) -> Self {
+        Self {
+            hits: vec![0; num_levels].into_boxed_slice(),
+            misses: vec![0; num_levels].into_boxed_slice(),
+            cold_accesses: 0,
+            feed_reloads: Vec::new(),
+        }
+    }
+
+    /// Record an access to a feed entry, given whether it was the first
+    /// access to that entry, and which cache level it hit (if any)
+    pub(super) fn record_access(
+        &mut self,
+        entry: Entry,
+        first_access: bool,
+        hit_level: Option<usize>,
+    ) {
+        if self.feed_reloads.len() <= entry as usize {
+            self.feed_reloads.resize(entry as usize + 1, 0);
+        }
+        if first_access {
+            self.cold_accesses += 1;
+            return;
+        }
+        let num_levels = self.hits.len();
+        let hit_level = hit_level.unwrap_or(num_levels);
+        for misses in self.misses[..hit_level].iter_mut() {
+            *misses += 1;
+        }
+        if hit_level < num_levels {
+            self.hits[hit_level] += 1;
+        }
+        if hit_level > 0 {
+            self.feed_reloads[entry as usize] += 1;
+        }
+    }
+}
+//
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, (hits, misses)) in self.hits.iter().zip(self.misses.iter()).enumerate() {
+            write!(f, "L{}: {} hits, {} misses; ", idx + 1, hits, misses)?;
+        }
+        write!(
+            f,
+            "{} cold accesses; reloads per feed {:?}",
+            self.cold_accesses, self.feed_reloads
+        )
+    }
+}
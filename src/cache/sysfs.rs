@@ -0,0 +1,427 @@
+//! Discovery of the host's cache hierarchy from Linux's sysfs
+
+use super::{
+    hierarchy::HierarchyError, CacheHierarchy, CacheLevel, Cost, SetAssociativity, COST_GRANULARITY,
+};
+use std::path::{Path, PathBuf};
+
+/// Default cost of missing each cache level and hitting the next one, used
+/// for every cache level except the last one
+///
+/// These are ballpark figures from recent x86 CPUs (see ZEN3_CACHE_LEVELS),
+/// expressed in units of L1 cache misses.
+///
+const DEFAULT_MISS_COSTS: [Cost; 3] = [
+    Cost::from_bits(COST_GRANULARITY),
+    Cost::from_bits(5 * COST_GRANULARITY),
+    Cost::from_bits(15 * COST_GRANULARITY),
+];
+
+/// Default cost of missing the last cache level and going to main memory
+const DEFAULT_MEMORY_MISS_COST: Cost = Cost::from_bits(30 * COST_GRANULARITY);
+
+/// Default cost of transferring each cache line after missing a cache level,
+/// except for the last one, in the same units as DEFAULT_MISS_COSTS
+const DEFAULT_LINE_TRANSFER_COSTS: [f32; 3] = [0.15, 0.25, 0.5];
+
+/// Default cost of transferring each cache line from main memory
+const DEFAULT_MEMORY_LINE_TRANSFER_COST: f32 = 1.0;
+
+impl CacheHierarchy {
+    /// Read the cache hierarchy of the host from sysfs
+    ///
+    /// `sysfs_root` is normally `/sys`, but can point to a copy of (or a fake)
+    /// sysfs directory tree. We look at the data and unified caches of the
+    /// lowest-numbered CPU, which is assumed to be representative of others.
+    ///
+    /// Since sysfs tells nothing about cache latencies, miss costs are set to
+    /// sensible defaults. Miss latencies can be overriden using
+    /// `with_miss_costs()`.
+    ///
+    pub fn from_sysfs(sysfs_root: impl AsRef<Path>) -> Result<Self, HierarchyError> {
+        // Find the lowest-numbered CPU
+        let cpu_root = sysfs_root.as_ref().join("devices/system/cpu");
+        let (cpu_idx, cpu_path) = read_dir(&cpu_root)?
+            .into_iter()
+            .filter_map(|(name, path)| {
+                let idx = name.strip_prefix("cpu")?.parse::<usize>().ok()?;
+                Some((idx, path))
+            })
+            .min()
+            .ok_or_else(|| sysfs_error(&cpu_root, "no CPU found"))?;
+
+        // Enumerate its data and unified caches
+        let mut caches = Vec::new();
+        for (name, path) in read_dir(&cpu_path.join("cache"))? {
+            if !name.starts_with("index") {
+                continue;
+            }
+            if let Some(cache) = SysfsCache::read(&path)? {
+                caches.push(cache);
+            }
+        }
+        caches.sort_by_key(|cache| cache.level);
+
+        // Translate them into cache level descriptions
+        let num_levels = caches.len();
+        let mut sharing = Vec::new();
+        let mut levels = Vec::with_capacity(num_levels);
+        for (idx, cache) in caches.into_iter().enumerate() {
+            if cache.level != idx + 1 {
+                return Err(sysfs_error(
+                    &cpu_path,
+                    &format!("expected one L{} data cache", idx + 1),
+                ));
+            }
+            if cache.shared_cpus > 1 {
+                sharing.push(format!(
+                    "L{} shared by {} CPUs",
+                    cache.level, cache.shared_cpus
+                ));
+            }
+            let (miss_cost, line_transfer_cost) = default_costs(idx, num_levels);
+            levels.push(CacheLevel {
+                capacity: cache.size,
+                miss_cost,
+                line_transfer_cost,
+                associativity: cache.associativity,
+            });
+        }
+        let mut name = format!("Host CPU{}", cpu_idx);
+        if !sharing.is_empty() {
+            name.push_str(&format!(" ({})", sharing.join(", ")));
+        }
+        Self::new(name, levels)
+    }
+
+    /// Override the miss cost of every cache level
+    pub fn with_miss_costs(self, miss_costs: &[Cost]) -> Result<Self, HierarchyError> {
+        if miss_costs.len() != self.levels.len() {
+            return Err(HierarchyError::MissCostCount {
+                expected: self.levels.len(),
+                actual: miss_costs.len(),
+            });
+        }
+        let levels = self
+            .levels
+            .into_iter()
+            .zip(miss_costs)
+            .map(|(level, &miss_cost)| CacheLevel { miss_cost, ..level })
+            .collect();
+        Self::new(self.name, levels)
+    }
+}
+
+/// Default miss cost and line transfer cost of a cache level, given its index
+/// and the number of cache levels
+pub(super) fn default_costs(idx: usize, num_levels: usize) -> (Cost, f32) {
+    if idx == num_levels - 1 {
+        (DEFAULT_MEMORY_MISS_COST, DEFAULT_MEMORY_LINE_TRANSFER_COST)
+    } else {
+        (
+            DEFAULT_MISS_COSTS
+                .get(idx)
+                .copied()
+                .unwrap_or(DEFAULT_MEMORY_MISS_COST),
+            DEFAULT_LINE_TRANSFER_COSTS
+                .get(idx)
+                .copied()
+                .unwrap_or(DEFAULT_MEMORY_LINE_TRANSFER_COST),
+        )
+    }
+}
+
+/// Cache as described by sysfs
+struct SysfsCache {
+    /// Level of the cache (1 for L1, 2 for L2...)
+    level: usize,
+
+    /// Capacity in bytes
+    size: usize,
+
+    /// Geometry, if the cache is set-associative
+    associativity: Option<SetAssociativity>,
+
+    /// Number of CPUs that share this cache
+    shared_cpus: usize,
+}
+//
+impl SysfsCache {
+    /// Read the description of a cache from its sysfs directory, ignoring
+    /// instruction caches
+    fn read(path: &Path) -> Result<Option<Self>, HierarchyError> {
+        if read_attribute(path, "type")? == "Instruction" {
+            return Ok(None);
+        }
+        let level = parse_number(path, "level")?;
+        let size = parse_size(path)?;
+
+        // Fully associative caches report zero ways, and some platforms do not
+        // report their caches' geometry at all. Others report a geometry that
+        // does not match the cache size, in which case we cannot simulate the
+        // cache's sets and treat it as fully associative.
+        let ways = parse_optional_number(path, "ways_of_associativity")?.unwrap_or(0);
+        let line_size = parse_optional_number(path, "coherency_line_size")?.unwrap_or(0);
+        let sets = parse_optional_number(path, "number_of_sets")?.unwrap_or(0);
+        let associativity = Some(SetAssociativity {
+            ways,
+            sets,
+            line_size,
+        })
+        .filter(|geometry| ways != 0 && line_size != 0 && sets != 0 && geometry.capacity() == size);
+
+        let shared_cpus = parse_cpu_list(path, &read_attribute(path, "shared_cpu_list")?)?;
+        Ok(Some(Self {
+            level,
+            size,
+            associativity,
+            shared_cpus,
+        }))
+    }
+}
+
+/// List the entries of a sysfs directory
+fn read_dir(path: &Path) -> Result<Vec<(String, PathBuf)>, HierarchyError> {
+    let io_error = |error: std::io::Error| sysfs_error(path, &error.to_string());
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(path).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if let Ok(name) = entry.file_name().into_string() {
+            entries.push((name, entry.path()));
+        }
+    }
+    Ok(entries)
+}
+
+/// Read a sysfs attribute, with trailing whitespace stripped
+fn read_attribute(dir: &Path, name: &str) -> Result<String, HierarchyError> {
+    let path = dir.join(name);
+    std::fs::read_to_string(&path)
+        .map(|contents| contents.trim_end().to_owned())
+        .map_err(|error| sysfs_error(&path, &error.to_string()))
+}
+
+/// Parse a sysfs attribute which contains a number
+fn parse_number(dir: &Path, name: &str) -> Result<usize, HierarchyError> {
+    let contents = read_attribute(dir, name)?;
+    contents
+        .parse()
+        .map_err(|_| sysfs_error(&dir.join(name), &format!("bad number {:?}", contents)))
+}
+
+/// Parse a sysfs attribute which contains a number, if it exists
+fn parse_optional_number(dir: &Path, name: &str) -> Result<Option<usize>, HierarchyError> {
+    if dir.join(name).exists() {
+        parse_number(dir, name).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parse a cache size attribute, which looks like "32K"
+fn parse_size(dir: &Path) -> Result<usize, HierarchyError> {
+    let contents = read_attribute(dir, "size")?;
+    let (digits, multiplier) = match contents.as_bytes().last() {
+        Some(b'K') => (&contents[..contents.len() - 1], 1024),
+        Some(b'M') => (&contents[..contents.len() - 1], 1024 * 1024),
+        Some(b'G') => (&contents[..contents.len() - 1], 1024 * 1024 * 1024),
+        _ => (&contents[..], 1),
+    };
+    digits
+        .parse::<usize>()
+        .map(|size| size * multiplier)
+        .map_err(|_| sysfs_error(&dir.join("size"), &format!("bad size {:?}", contents)))
+}
+
+/// Count the CPUs in a CPU list, which looks like "0-3,8-11"
+fn parse_cpu_list(dir: &Path, list: &str) -> Result<usize, HierarchyError> {
+    let bad_list = || {
+        sysfs_error(
+            &dir.join("shared_cpu_list"),
+            &format!("bad CPU list {:?}", list),
+        )
+    };
+    let mut num_cpus = 0;
+    for range in list.split(',') {
+        let mut bounds = range.splitn(2, '-').map(str::parse::<usize>);
+        let first = bounds.next().unwrap().map_err(|_| bad_list())?;
+        let last = bounds
+            .next()
+            .transpose()
+            .map_err(|_| bad_list())?
+            .unwrap_or(first);
+        if last < first {
+            return Err(bad_list());
+        }
+        num_cpus += last - first + 1;
+    }
+    Ok(num_cpus)
+}
+
+/// Report an error about a sysfs file or directory
+fn sysfs_error(path: &Path, message: &str) -> HierarchyError {
+    HierarchyError::Sysfs {
+        path: path.to_owned(),
+        message: message.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Fake sysfs directory tree, which is deleted when dropped
+    struct FakeSysfs(PathBuf);
+    //
+    impl FakeSysfs {
+        /// Create an empty tree with a unique name
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("cachot-sysfs-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("devices/system/cpu/cpufreq")).unwrap();
+            Self(root)
+        }
+
+        /// Add a cache to a CPU, given its attributes
+        fn add_cache(&self, cpu: usize, index: usize, attributes: &[(&str, &str)]) {
+            let path = self.0.join(format!(
+                "devices/system/cpu/cpu{}/cache/index{}",
+                cpu, index
+            ));
+            fs::create_dir_all(&path).unwrap();
+            for (name, contents) in attributes {
+                fs::write(path.join(name), format!("{}\n", contents)).unwrap();
+            }
+        }
+    }
+    //
+    impl Drop for FakeSysfs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Attributes of a cache with a known geometry
+    fn cache_attributes<'a>(
+        level: &'a str,
+        kind: &'a str,
+        size: &'a str,
+        [ways, sets]: [&'a str; 2],
+        shared_cpus: &'a str,
+    ) -> Vec<(&'a str, &'a str)> {
+        vec![
+            ("level", level),
+            ("type", kind),
+            ("size", size),
+            ("ways_of_associativity", ways),
+            ("number_of_sets", sets),
+            ("coherency_line_size", "64"),
+            ("shared_cpu_list", shared_cpus),
+        ]
+    }
+
+    #[test]
+    fn reads_cache_hierarchy() {
+        let sysfs = FakeSysfs::new("accept");
+        for cpu in 0..2 {
+            let cpu_list = cpu.to_string();
+            sysfs.add_cache(
+                cpu,
+                0,
+                &cache_attributes("1", "Data", "32K", ["8", "64"], &cpu_list),
+            );
+            sysfs.add_cache(
+                cpu,
+                1,
+                &cache_attributes("1", "Instruction", "32K", ["8", "64"], &cpu_list),
+            );
+            sysfs.add_cache(
+                cpu,
+                2,
+                &cache_attributes("2", "Unified", "512K", ["8", "1024"], &cpu_list),
+            );
+            // L3 with a geometry that does not match its size
+            sysfs.add_cache(
+                cpu,
+                3,
+                &cache_attributes("3", "Unified", "30M", ["16", "32768"], "0-1"),
+            );
+        }
+
+        let hierarchy = CacheHierarchy::from_sysfs(&sysfs.0).unwrap();
+        assert_eq!(hierarchy.name, "Host CPU0 (L3 shared by 2 CPUs)");
+        let geometry = |ways, sets| {
+            Some(SetAssociativity {
+                ways,
+                sets,
+                line_size: 64,
+            })
+        };
+        let capacities_and_geometries = hierarchy
+            .levels
+            .iter()
+            .map(|level| (level.capacity, level.associativity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            capacities_and_geometries,
+            vec![
+                (32 * 1024, geometry(8, 64)),
+                (512 * 1024, geometry(8, 1024)),
+                (30 * 1024 * 1024, None),
+            ]
+        );
+        for (idx, level) in hierarchy.levels.iter().enumerate() {
+            assert_eq!(
+                (level.miss_cost, level.line_transfer_cost),
+                default_costs(idx, hierarchy.levels.len())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_cache_hierarchy() {
+        // Missing L2
+        let sysfs = FakeSysfs::new("missing-level");
+        sysfs.add_cache(
+            0,
+            0,
+            &cache_attributes("1", "Data", "32K", ["8", "64"], "0"),
+        );
+        sysfs.add_cache(
+            0,
+            1,
+            &cache_attributes("3", "Unified", "32M", ["16", "32768"], "0-7"),
+        );
+        assert!(CacheHierarchy::from_sysfs(&sysfs.0).is_err());
+
+        // Malformed attributes
+        let bad_attributes = [
+            ("size", "32Q"),
+            ("level", "one"),
+            ("shared_cpu_list", "3-1"),
+            ("number_of_sets", "-64"),
+        ];
+        for (name, contents) in bad_attributes.iter() {
+            let sysfs = FakeSysfs::new(&format!("bad-{}", name));
+            let mut attributes = cache_attributes("1", "Data", "32K", ["8", "64"], "0");
+            for attribute in attributes.iter_mut() {
+                if attribute.0 == *name {
+                    attribute.1 = contents;
+                }
+            }
+            sysfs.add_cache(0, 0, &attributes);
+            assert!(
+                CacheHierarchy::from_sysfs(&sysfs.0).is_err(),
+                "Accepted {} = {:?}",
+                name,
+                contents
+            );
+        }
+
+        // No CPU at all
+        let sysfs = FakeSysfs::new("no-cpu");
+        assert!(CacheHierarchy::from_sysfs(&sysfs.0).is_err());
+    }
+}
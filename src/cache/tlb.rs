@@ -0,0 +1,124 @@
+//! Translation lookaside buffer model
+
+use super::{Cost, CostOverflow, Entry, FeedLayout};
+use crate::capacity::CompactUint;
+
+/// Translation lookaside buffer (TLB) configuration
+///
+/// Every access to a feed entry touches the memory pages that this entry
+/// overlaps, and the TLB must hold the translations of these pages. Since feed
+/// buffers are laid out at a regular stride in memory, neighboring feeds may
+/// share pages, which matters a lot with huge pages.
+///
+/// The TLB is modeled as a fully associative cache of page translations with
+/// an LRU replacement policy, which sees the same access stream as the CPU
+/// caches. Output entries, if any, are not accounted for.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tlb {
+    /// Size of a memory page in bytes
+    pub page_size: usize,
+
+    /// Number of page translations that the TLB can hold
+    pub entries: usize,
+
+    /// Cost of a TLB miss, in units of L1 cache miss latency (like
+    /// `CacheLevel::miss_cost`)
+    pub miss_cost: f32,
+}
+//
+impl Tlb {
+    /// TLB with sensible defaults for a certain page size
+    ///
+    /// These are the figures of the first-level data TLB of Zen 3, which holds
+    /// 64 translations whatever the page size, and whose misses mostly hit the
+    /// second-level TLB at a cost similar to that of an L1 cache miss.
+    ///
+    pub fn new(page_size: usize) -> Self {
+        Self {
+            page_size,
+            entries: 64,
+            miss_cost: 1.0,
+        }
+    }
+}
+
+/// TLB model, specialized for a certain layout of feed buffers in memory
+#[derive(Clone, Debug)]
+pub(super) struct TlbModel {
+    /// Number of page translations that the TLB can hold
+    entries: usize,
+
+    /// Cost of a TLB miss, in units of L1 cache misses
+    miss_cost: f32,
+
+    /// First page and end page (excluded) of each feed entry
+    feed_pages: Box<[(usize, usize)]>,
+}
+//
+impl TlbModel {
+    /// Specialize a TLB configuration for a certain feed buffer layout, given
+    /// the absolute cost of an L1 cache miss
+    pub fn new(tlb: &Tlb, layout: &FeedLayout, cost_unit: f32) -> Self {
+        assert!(tlb.page_size > 0, "Page size should not be zero");
+        assert!(tlb.entries > 0, "TLB should hold at least one translation");
+        let feed_pages = (0..layout.num_feeds())
+            .map(|feed| {
+                let bytes = layout.entry_bytes(feed);
+                (
+                    bytes.start / tlb.page_size,
+                    bytes.end.div_ceil(tlb.page_size),
+                )
+            })
+            .collect();
+        Self {
+            entries: tlb.entries,
+            miss_cost: tlb.miss_cost / cost_unit,
+            feed_pages,
+        }
+    }
+
+    /// Compute the cost of the TLB misses caused by accessing an entry, given
+    /// the time at which each entry was last accessed (0 if never), if it is
+    /// within Cost range
+    pub fn access_cost<Clock: CompactUint>(
+        &self,
+        last_accesses: &[Clock],
+        entry: Entry,
+    ) -> Result<Cost, CostOverflow> {
+        let (first_page, end_page) = self.feed_pages[entry as usize];
+        let num_misses = (first_page..end_page)
+            .filter(|&page| !self.holds(last_accesses, page))
+            .count();
+        Cost::checked_from_num(num_misses as f32 * self.miss_cost)
+            .ok_or_else(CostOverflow::new::<Cost>)
+    }
+
+    /// Tell whether the TLB still holds the translation of a page
+    fn holds<Clock: CompactUint>(&self, last_accesses: &[Clock], page: usize) -> bool {
+        // Find when the page was last accessed, via any feed that overlaps it
+        let last_use = self
+            .feed_pages
+            .iter()
+            .zip(last_accesses)
+            .filter(|&(&(first_page, end_page), _)| (first_page..end_page).contains(&page))
+            .map(|(_pages, &access_time)| access_time)
+            .max()
+            .unwrap_or_default();
+        if last_use == Clock::default() {
+            return false;
+        }
+
+        // Count the distinct pages that were accessed since then. Feed pages
+        // are sorted by feed index, and only neighboring feeds can overlap.
+        let mut num_pages = 0;
+        let mut counted_end = 0;
+        for (&(first_page, end_page), &access_time) in self.feed_pages.iter().zip(last_accesses) {
+            if access_time > last_use {
+                num_pages += end_page - first_page.max(counted_end).min(end_page);
+                counted_end = counted_end.max(end_page);
+            }
+        }
+        num_pages < self.entries
+    }
+}
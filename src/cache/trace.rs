@@ -0,0 +1,316 @@
+//! Memory access traces, which can be replayed through the cache model
+
+use super::{CacheSimulation, Entry};
+use crate::capacity::{FeedCapacity, LargestCapacity};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    io,
+    path::Path,
+    str::FromStr,
+};
+
+/// Size of the accesses of Dinero traces that do not specify it, in bytes
+const DINERO_DEFAULT_SIZE: u64 = 4;
+
+/// Format of a memory access trace file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceFormat {
+    /// One `<address> <size> <r|w>` access per line, where the address is
+    /// hexadecimal (with an optional 0x prefix) and the size is in bytes
+    Text,
+
+    /// Dinero "din" format, with one `<label> <address> [<size>]` access per
+    /// line, where the label is 0 for reads, 1 for writes, 2 for instruction
+    /// fetches, 3 for escapes and 4 for cache flushes, and the address is
+    /// hexadecimal. Only data reads and writes are replayed.
+    Dinero,
+}
+//
+impl FromStr for TraceFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "text" => Ok(Self::Text),
+            "din" | "dinero" => Ok(Self::Dinero),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Memory access trace, mapped into a stream of cache entry accesses
+///
+/// Addresses are split into blocks of a certain granularity, which normally
+/// is the cache model's entry size, and each distinct block is mapped into a
+/// cache entry, in order of first access. An access that straddles several
+/// blocks accesses all of the corresponding entries.
+///
+/// The cache model does not distinguish reads from writes to feed entries,
+/// only output entries are written to. So writes are replayed as reads, but
+/// counted separately.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    /// Sequence of entry accesses
+    entries: Vec<Entry>,
+
+    /// Number of distinct entries
+    num_entries: usize,
+
+    /// Number of replayed trace records that were reads
+    num_reads: usize,
+
+    /// Number of replayed trace records that were writes
+    num_writes: usize,
+
+    /// Number of trace records that were not replayed
+    num_ignored: usize,
+}
+//
+impl Trace {
+    /// Load a trace from a file
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        format: TraceFormat,
+        granularity: usize,
+    ) -> Result<Self, TraceError> {
+        let source = std::fs::read_to_string(path).map_err(TraceError::Io)?;
+        Self::parse(&source, format, granularity)
+    }
+
+    /// Parse the contents of a trace file
+    pub fn parse(
+        source: &str,
+        format: TraceFormat,
+        granularity: usize,
+    ) -> Result<Self, TraceError> {
+        assert!(granularity > 0, "Trace granularity should not be zero");
+        let mut trace = Self::default();
+        let mut block_entries = HashMap::<u64, Entry>::new();
+        for (idx, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let syntax_error = |message: &str| TraceError::Syntax {
+                line: idx + 1,
+                message: message.to_owned(),
+            };
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let (address, size, write) = match format {
+                TraceFormat::Text => {
+                    let (address, size, kind) = match fields[..] {
+                        [address, size, kind] => (address, size, kind),
+                        _ => return Err(syntax_error("expected an address, a size and r or w")),
+                    };
+                    let write = match kind {
+                        "r" | "R" => false,
+                        "w" | "W" => true,
+                        _ => return Err(syntax_error("access kind should be r or w")),
+                    };
+                    (parse_address(address), size.parse::<u64>().ok(), write)
+                }
+                TraceFormat::Dinero => {
+                    let (label, address, size) = match fields[..] {
+                        [label, address] => (label, address, None),
+                        [label, address, size] => (label, address, Some(size)),
+                        _ => return Err(syntax_error("expected a label, an address and a size")),
+                    };
+                    let write = match label {
+                        "0" => false,
+                        "1" => true,
+                        "2" | "3" | "4" => {
+                            trace.num_ignored += 1;
+                            continue;
+                        }
+                        _ => return Err(syntax_error("label should be between 0 and 4")),
+                    };
+                    let size = size.map_or(Some(DINERO_DEFAULT_SIZE), |size| size.parse().ok());
+                    (parse_address(address), size, write)
+                }
+            };
+            let (address, size) = match (address, size) {
+                (Some(address), Some(size)) if size > 0 => (address, size),
+                _ => return Err(syntax_error("invalid address or size")),
+            };
+
+            let first_block = address / granularity as u64;
+            let last_block = address.saturating_add(size - 1) / granularity as u64;
+            for block in first_block..=last_block {
+                let next_entry = block_entries.len();
+                let entry = *block_entries.entry(block).or_insert(next_entry as Entry);
+                if block_entries.len() > LargestCapacity::MAX_FEEDS as usize {
+                    return Err(TraceError::TooManyEntries {
+                        max: LargestCapacity::MAX_FEEDS as usize,
+                    });
+                }
+                if trace.entries.len() == <CacheSimulation>::MAX_ACCESSES {
+                    return Err(TraceError::TooManyAccesses {
+                        max: <CacheSimulation>::MAX_ACCESSES,
+                    });
+                }
+                trace.entries.push(entry);
+            }
+            if write {
+                trace.num_writes += 1;
+            } else {
+                trace.num_reads += 1;
+            }
+        }
+        trace.num_entries = block_entries.len();
+        Ok(trace)
+    }
+
+    /// Sequence of entry accesses
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries[..]
+    }
+
+    /// Number of distinct entries that are accessed
+    pub fn num_entries(&self) -> usize {
+        self.num_entries
+    }
+
+    /// Number of replayed trace records that were reads
+    pub fn num_reads(&self) -> usize {
+        self.num_reads
+    }
+
+    /// Number of replayed trace records that were writes
+    pub fn num_writes(&self) -> usize {
+        self.num_writes
+    }
+
+    /// Number of trace records that were not replayed, such as instruction
+    /// fetches in Dinero traces
+    pub fn num_ignored(&self) -> usize {
+        self.num_ignored
+    }
+}
+
+/// Parse a hexadecimal address, with an optional 0x prefix
+fn parse_address(address: &str) -> Option<u64> {
+    let digits = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .unwrap_or(address);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+/// Error while loading a memory access trace
+#[derive(Debug)]
+pub enum TraceError {
+    /// The trace file could not be read
+    Io(io::Error),
+
+    /// The trace file is not correctly formatted
+    Syntax { line: usize, message: String },
+
+    /// The trace accesses more distinct entries than can be simulated
+    TooManyEntries { max: usize },
+
+    /// The trace has more entry accesses than can be simulated
+    TooManyAccesses { max: usize },
+}
+//
+impl Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "failed to read trace file: {}", error),
+            Self::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            Self::TooManyEntries { max } => write!(
+                f,
+                "trace accesses more than {} distinct entries, try a coarser granularity",
+                max
+            ),
+            Self::TooManyAccesses { max } => {
+                write!(f, "trace has more than {} entry accesses", max)
+            }
+        }
+    }
+}
+//
+impl std::error::Error for TraceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_text_traces() {
+        let trace = Trace::parse(
+            "# Comment\n\
+             0x100 8 r\n\
+             \n\
+             0X13c 8 W\n\
+             1f8 16 r\n",
+            TraceFormat::Text,
+            64,
+        )
+        .unwrap();
+        // The last two accesses straddle blocks 4 and 5, then 7 and 8
+        assert_eq!(trace.entries(), &[0, 0, 1, 2, 3]);
+        assert_eq!(trace.num_entries(), 4);
+        assert_eq!(trace.num_reads(), 2);
+        assert_eq!(trace.num_writes(), 1);
+        assert_eq!(trace.num_ignored(), 0);
+    }
+
+    #[test]
+    fn parses_dinero_traces() {
+        let trace = Trace::parse(
+            "2 0 4\n\
+             0 40\n\
+             1 7c 8\n\
+             3 0\n\
+             0 40 1\n",
+            TraceFormat::Dinero,
+            64,
+        )
+        .unwrap();
+        // The second data access straddles blocks 1 and 2
+        assert_eq!(trace.entries(), &[0, 0, 1, 0]);
+        assert_eq!(trace.num_entries(), 2);
+        assert_eq!(trace.num_reads(), 2);
+        assert_eq!(trace.num_writes(), 1);
+        assert_eq!(trace.num_ignored(), 2);
+    }
+
+    #[test]
+    fn rejects_bad_traces() {
+        let syntax_error_line = |source, format| match Trace::parse(source, format, 64) {
+            Err(TraceError::Syntax { line, .. }) => line,
+            other => panic!("Expected a syntax error, got {:?}", other),
+        };
+        assert_eq!(
+            syntax_error_line("0x100 8 r\n0x100 8", TraceFormat::Text),
+            2
+        );
+        assert_eq!(syntax_error_line("0x100 8 x", TraceFormat::Text), 1);
+        assert_eq!(syntax_error_line("0xfoo 8 r", TraceFormat::Text), 1);
+        assert_eq!(syntax_error_line("0x100 0 r", TraceFormat::Text), 1);
+        assert_eq!(syntax_error_line("0x100 -8 r", TraceFormat::Text), 1);
+        assert_eq!(syntax_error_line("\n5 100", TraceFormat::Dinero), 2);
+        assert_eq!(syntax_error_line("0 100 4 2", TraceFormat::Dinero), 1);
+        assert_eq!(syntax_error_line("0 100 four", TraceFormat::Dinero), 1);
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(parse_address("0x1F"), Some(0x1f));
+        assert_eq!(parse_address("0X1f"), Some(0x1f));
+        assert_eq!(parse_address("1f"), Some(0x1f));
+        assert_eq!(parse_address(""), None);
+        assert_eq!(parse_address("0x"), None);
+        assert_eq!(parse_address("0x1g"), None);
+        assert_eq!(parse_address("ffffffffffffffff0"), None);
+    }
+}
@@ -0,0 +1,227 @@
+//! Sizing of the data structures that depend on the number of feeds
+
+use crate::{
+    brute_force::{FixedPairBitmap, PackedFeedPair, PairBitmap, RuntimePairBitmap},
+    FeedIdx,
+};
+use static_assertions::const_assert;
+use std::{fmt::Debug, marker::PhantomData};
+
+/// Machine word size in bits
+pub const WORD_SIZE: u32 = (std::mem::size_of::<usize>() * 8) as u32;
+
+/// Integer division with upper rounding
+pub const fn div_round_up(num: usize, denom: usize) -> usize {
+    (num / denom) + (num % denom != 0) as usize
+}
+
+/// Number of unordered feed pairs for a certain number of feeds
+pub const fn num_unordered_pairs(num_feeds: FeedIdx) -> usize {
+    num_feeds as usize * (num_feeds as usize + 1) / 2
+}
+
+/// Upper bound on the number of feeds, and associated data structure sizes
+///
+/// To reduce memory allocation and improve data locality, we would like to use
+/// fixed-sized data structures when the size depends on the number of feeds, as
+/// this parameter is known at compile time. But we would also like these data
+/// structures to be as small as possible, since the brute force search's
+/// partial paths contain them and dominate our memory bandwidth usage, and
+/// what "as small as possible" means depends on the number of feeds.
+///
+/// Const generics are not powerful enough to compute these types from the
+/// number of feeds, so instead each supported upper bound is a `MaxFeeds`
+/// variant which spells them out, and code that must stay compact is generic
+/// over this trait. The smallest variant that can hold a certain number of
+/// feeds should be used.
+///
+/// Beyond the largest `MaxFeeds` variant, the `RuntimeFeeds` variants size
+/// these data structures at run time instead, which is less efficient but
+/// works for any number of feeds.
+///
+pub trait FeedCapacity: Clone + 'static {
+    /// Maximal number of feeds
+    const MAX_FEEDS: FeedIdx;
+
+    /// Maximal number of feed pairs
+    const MAX_PAIRS: usize = Self::MAX_FEEDS as usize * Self::MAX_FEEDS as usize;
+
+    /// Maximal number of unordered feed pairs
+    const MAX_UNORDERED_PAIRS: usize = num_unordered_pairs(Self::MAX_FEEDS);
+
+    /// Tell whether a path through `num_pairs` pairs of `num_feeds` feeds fits
+    ///
+    /// Clocks and path lengths are sized for paths through the unordered feed
+    /// pairs, so longer paths (e.g. through ordered pairs) need a larger
+    /// capacity than their number of feeds suggests.
+    ///
+    fn can_hold(num_feeds: FeedIdx, num_pairs: usize) -> bool {
+        num_feeds <= Self::MAX_FEEDS && num_pairs <= Self::MAX_UNORDERED_PAIRS
+    }
+
+    /// Cache simulation clock, which should be able to hold the max cache
+    /// timestamp, that is three times the number of unordered pairs (two feed
+    /// entries and one output entry per pair), plus one.
+    type Clock: CompactUint;
+
+    /// Last access time of each feed
+    type FeedClocks: FeedClocks<Self::Clock>;
+
+    /// Length of a path through the unordered feed pairs
+    type PathLen: CompactUint;
+
+    /// Feed pair, packed in a single integer
+    type PackedFeedPair: PackedFeedPair;
+
+    /// Bitmap with one bit per feed pair
+    type PairBitmap: PairBitmap;
+}
+
+/// Storage for the last access time of each feed
+pub trait FeedClocks<Clock: CompactUint>: Clone + AsRef<[Clock]> + AsMut<[Clock]> {
+    /// Set up the clocks of a certain number of feeds, which were never
+    /// accessed
+    fn new(num_feeds: FeedIdx) -> Self;
+}
+//
+impl<Clock: CompactUint, const N: usize> FeedClocks<Clock> for [Clock; N] {
+    fn new(num_feeds: FeedIdx) -> Self {
+        debug_assert!(num_feeds as usize <= N);
+        [Clock::default(); N]
+    }
+}
+//
+impl<Clock: CompactUint> FeedClocks<Clock> for Box<[Clock]> {
+    fn new(num_feeds: FeedIdx) -> Self {
+        vec![Clock::default(); num_feeds as usize].into_boxed_slice()
+    }
+}
+
+/// Unsigned integer type that is kept as small as the feed capacity allows
+pub trait CompactUint: Copy + Debug + Default + Ord {
+    /// Largest value of this type, as a usize
+    const MAX: usize;
+
+    /// Convert from usize, which must be in range
+    fn from_usize(x: usize) -> Self;
+
+    /// Convert to usize
+    fn to_usize(self) -> usize;
+
+    /// Increment by one
+    fn increment(&mut self);
+}
+//
+macro_rules! impl_compact_uint {
+    ($($uint:ty),*) => {
+        $(
+            impl CompactUint for $uint {
+                const MAX: usize = <$uint>::MAX as usize;
+
+                #[inline(always)]
+                fn from_usize(x: usize) -> Self {
+                    debug_assert!(x <= <Self as CompactUint>::MAX);
+                    x as Self
+                }
+
+                #[inline(always)]
+                fn to_usize(self) -> usize {
+                    self as usize
+                }
+
+                #[inline(always)]
+                fn increment(&mut self) {
+                    *self += 1;
+                }
+            }
+        )*
+    };
+}
+//
+impl_compact_uint!(u8, u16, u32);
+
+/// Upper bound on the number of feeds
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MaxFeeds<const N: FeedIdx>;
+//
+macro_rules! impl_feed_capacity {
+    ($($max_feeds:literal => ($clock:ty, $path_len:ty, $packed_feed_pair:ty)),*) => {
+        $(
+            impl FeedCapacity for MaxFeeds<$max_feeds> {
+                const MAX_FEEDS: FeedIdx = $max_feeds;
+                type Clock = $clock;
+                type FeedClocks = [$clock; $max_feeds];
+                type PathLen = $path_len;
+                type PackedFeedPair = $packed_feed_pair;
+                type PairBitmap = FixedPairBitmap<
+                    $max_feeds,
+                    { div_round_up(<MaxFeeds<$max_feeds>>::MAX_PAIRS, WORD_SIZE as usize) },
+                >;
+            }
+            //
+            const_assert!(
+                <$clock>::MAX as usize >= 3 * <MaxFeeds<$max_feeds>>::MAX_UNORDERED_PAIRS + 1
+            );
+            const_assert!(
+                <$path_len>::MAX as usize >= <MaxFeeds<$max_feeds>>::MAX_UNORDERED_PAIRS
+            );
+            const_assert!(
+                1 << <$packed_feed_pair as PackedFeedPair>::FEED_BITS >= $max_feeds
+            );
+        )*
+    };
+}
+//
+impl_feed_capacity!(
+    8 => (u8, u8, u8),
+    16 => (u16, u8, u8),
+    32 => (u16, u16, u16)
+);
+
+/// Feed capacity that is only known at run time
+///
+/// Per-feed data structures are heap-allocated and sized for the actual number
+/// of feeds, and integers are of type `U`. Wider integers support more feeds,
+/// at the expense of a larger memory footprint.
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RuntimeFeeds<U>(PhantomData<U>);
+//
+/// Largest number of feeds whose cache simulation clock fits in an integer
+/// whose largest value is `max_clock`
+const fn max_feeds_for_clock(max_clock: usize) -> FeedIdx {
+    let mut num_feeds = FeedIdx::MAX;
+    while 3 * num_unordered_pairs(num_feeds) + 1 > max_clock {
+        num_feeds -= 1;
+    }
+    num_feeds
+}
+//
+macro_rules! impl_runtime_capacity {
+    ($($uint:ty),*) => {
+        $(
+            impl FeedCapacity for RuntimeFeeds<$uint> {
+                const MAX_FEEDS: FeedIdx = max_feeds_for_clock(<$uint>::MAX as usize);
+                type Clock = $uint;
+                type FeedClocks = Box<[$uint]>;
+                type PathLen = $uint;
+                type PackedFeedPair = $uint;
+                type PairBitmap = RuntimePairBitmap;
+            }
+            //
+            const_assert!(
+                <$uint>::MAX as usize >= <RuntimeFeeds<$uint>>::MAX_UNORDERED_PAIRS
+            );
+            const_assert!(
+                1 << <$uint as PackedFeedPair>::FEED_BITS >= <RuntimeFeeds<$uint>>::MAX_FEEDS as usize
+            );
+        )*
+    };
+}
+//
+impl_runtime_capacity!(u16, u32);
+
+/// Feed capacity that can hold any number of feeds
+pub type LargestCapacity = RuntimeFeeds<u32>;
+//
+const_assert!(LargestCapacity::MAX_FEEDS == FeedIdx::MAX);
@@ -1,39 +1,504 @@
 mod brute_force;
 pub(crate) mod cache;
+mod capacity;
+mod pair_domain;
 mod pair_locality;
 
-use crate::pair_locality::PairLocalityTester;
+use crate::{
+    cache::{
+        replacement::{self, Lru, ReplacementPolicy},
+        CacheHierarchy, CacheModel, Cost, CostAccumulator, FeedLayout, Inclusion, LatencyCurve,
+        MemoryParallelism, MultiCoreModel, OutputStores, Prefetcher, Tlb, Trace, TraceFormat,
+        WideCost,
+    },
+    pair_domain::PairDomain,
+    pair_locality::{PairLocalityTester, StackDistances},
+};
 use genawaiter::{stack::let_gen, yield_};
 use space_filler::{hilbert, morton, CurveIdx};
-use std::time::Duration;
+use std::{ops::Range, rc::Rc, time::Duration};
 
 /// Integer type used for counting radio feeds
 type FeedIdx = space_filler::Coordinate;
 
-/// Upper bound on the number of feeds that will be used
+/// Command-line options
 ///
-/// To reduce memory allocation and improve data locality, we would like to use
-/// fixed-sized data structures when the size depends on the number of feeds, as
-/// this parameter is known at compile time.
+/// Usage: cachot [--set-associative] [--replacement-policy <name>]
+///               [--inclusion <inclusive|exclusive|NINE>] [--prefetch]
+///               [--mlp <max overlapping misses>[,<window in feed pairs>]]
+///               [--output-size <bytes> [--streaming-stores]]
+///               [--cache-preset <name> | --cache-file <path> | --cache-sysfs]
+///               [--miss-costs <L1 cost>,<L2 cost>,...] [--latency-curve <CSV path>]
+///               [--threads <count>] [--num-feeds <count>]
+///               [--domain <triangle|strict-triangle|square|bipartite:<N>x<M>>
+///                [--entry-size-ratio <set B entry size / set A entry size>]]
+///               [--tlb <page size>[,<entries>[,<miss cost>]]]
+///               [--trace <path> [--trace-format <text|din>]
+///                [--trace-granularity <bytes>]]
 ///
-/// Unfortunately, Rust does not have const generics yet, and even the
-/// `min_const_generics` version that will be stabilized soon-ish is not
-/// powerful enough as it does not allow us to have a data structure whose size
-/// is a function of the number of feeds (such as, for example, a bitvec whose
-/// number of bits is equal to the number of feed pairs).
+/// If a memory access trace is specified, it is replayed through the cache
+/// model instead of testing feed pair iterators.
 ///
-/// As a compromise until we get there, we will use an upper bound on the number
-/// of feeds that will be used in tests.
+/// The bipartite domain crosses a set A of N feeds with a set B of M feeds,
+/// which sets the number of feeds to N + M. The entries of set B may have a
+/// different size than those of set A.
 ///
-pub(crate) const MAX_FEEDS: FeedIdx = 8 /* 16 */;
+struct Options {
+    /// Simulate set-associative caches instead of fully associative ones
+    set_associative: bool,
 
-/// Maximum number of feed pairs
-const MAX_PAIRS: usize = MAX_FEEDS as usize * MAX_FEEDS as usize;
+    /// Replacement policy of the simulated caches
+    replacement_policy: Rc<dyn ReplacementPolicy>,
 
-/// Maximum number of ordered feed pairs
-const _MAX_UNORDERED_PAIRS: usize = MAX_FEEDS as usize * (MAX_FEEDS as usize + 1) / 2;
+    /// Relationship between the contents of successive cache levels
+    inclusion: Inclusion,
+
+    /// Hardware prefetcher of the simulated caches, if any
+    prefetcher: Option<Prefetcher>,
+
+    /// Memory-level parallelism of the simulated CPU, if miss latencies may
+    /// overlap instead of adding up
+    memory_parallelism: Option<MemoryParallelism>,
+
+    /// Size of the per-pair output entries in bytes, if they are modeled
+    output_size: Option<usize>,
+
+    /// How the per-pair output entries are written to memory
+    output_stores: OutputStores,
+
+    /// Simulated cache hierarchy
+    cache_hierarchy: CacheHierarchy,
+
+    /// Measured latency curve, if it replaces the miss costs of cache levels
+    latency_curve: Option<(String, LatencyCurve)>,
+
+    /// Number of threads that share the last cache level
+    num_threads: usize,
+
+    /// Number of feeds to be tested, if not the default test configurations
+    num_feeds: Option<FeedIdx>,
+
+    /// Set of feed pairs that iteration schemes must go through
+    domain: PairDomain,
+
+    /// Size of the entries of feed set B relative to those of feed set A, if
+    /// the bipartite domain is used and entries have different sizes
+    entry_size_ratio: Option<f32>,
+
+    /// Translation lookaside buffer, if it is modeled
+    tlb: Option<Tlb>,
+
+    /// Memory access trace to be replayed, if any
+    trace: Option<String>,
+
+    /// Format of the memory access trace
+    trace_format: TraceFormat,
+
+    /// Size of the blocks of memory that trace accesses are mapped into,
+    /// which are simulated as cache entries
+    trace_granularity: usize,
+}
+//
+impl Options {
+    /// Parse the command-line options
+    fn from_args() -> Self {
+        let mut options = Self {
+            set_associative: false,
+            replacement_policy: Rc::new(Lru),
+            inclusion: Inclusion::Inclusive,
+            prefetcher: None,
+            memory_parallelism: None,
+            output_size: None,
+            output_stores: OutputStores::WriteAllocate,
+            cache_hierarchy: CacheHierarchy::from_preset("Zen3")
+                .expect("The Zen3 preset should be valid"),
+            latency_curve: None,
+            num_threads: 1,
+            num_feeds: None,
+            domain: PairDomain::Triangle,
+            entry_size_ratio: None,
+            tlb: None,
+            trace: None,
+            trace_format: TraceFormat::Text,
+            trace_granularity: 4096,
+        };
+        let mut miss_costs = None;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match &*arg {
+                "--set-associative" => options.set_associative = true,
+                "--replacement-policy" => {
+                    let name = args
+                        .next()
+                        .expect("--replacement-policy expects a policy name");
+                    options.replacement_policy = replacement::from_name(&name)
+                        .unwrap_or_else(|| panic!("Unknown replacement policy {:?}", name));
+                }
+                "--inclusion" => {
+                    let name = args.next().expect("--inclusion expects a hierarchy mode");
+                    options.inclusion = Inclusion::from_name(&name)
+                        .unwrap_or_else(|| panic!("Unknown hierarchy mode {:?}", name));
+                }
+                "--prefetch" => options.prefetcher = Some(Prefetcher::default()),
+                "--mlp" => {
+                    let spec = args.next().expect("--mlp expects a number of misses");
+                    let mut fields = spec.split(',').map(str::trim);
+                    let mut memory_parallelism = MemoryParallelism::default();
+                    let max_misses = fields.next().unwrap();
+                    memory_parallelism.max_misses = max_misses
+                        .parse()
+                        .ok()
+                        .filter(|&max_misses| max_misses > 0)
+                        .unwrap_or_else(|| panic!("Invalid number of misses {:?}", max_misses));
+                    if let Some(window) = fields.next() {
+                        memory_parallelism.window = window
+                            .parse()
+                            .ok()
+                            .filter(|&window| window > 0)
+                            .unwrap_or_else(|| panic!("Invalid MLP window {:?}", window));
+                    }
+                    options.memory_parallelism = Some(memory_parallelism);
+                }
+                "--output-size" => {
+                    let size = args.next().expect("--output-size expects a size in bytes");
+                    options.output_size = Some(
+                        size.parse()
+                            .unwrap_or_else(|_| panic!("Invalid output entry size {:?}", size)),
+                    );
+                }
+                "--streaming-stores" => options.output_stores = OutputStores::NonTemporal,
+                "--cache-preset" => {
+                    let name = args.next().expect("--cache-preset expects a preset name");
+                    options.cache_hierarchy = CacheHierarchy::from_preset(&name)
+                        .unwrap_or_else(|e| panic!("Failed to load cache preset: {}", e));
+                }
+                "--cache-file" => {
+                    let path = args.next().expect("--cache-file expects a file path");
+                    options.cache_hierarchy = CacheHierarchy::from_file(&path)
+                        .unwrap_or_else(|e| panic!("Failed to load cache file {}: {}", path, e));
+                }
+                "--cache-sysfs" => {
+                    options.cache_hierarchy = CacheHierarchy::from_sysfs("/sys")
+                        .unwrap_or_else(|e| panic!("Failed to read host cache hierarchy: {}", e));
+                }
+                "--miss-costs" => {
+                    let costs = args.next().expect("--miss-costs expects a list of costs");
+                    miss_costs = Some(
+                        costs
+                            .split(',')
+                            .map(|cost| {
+                                cost.trim()
+                                    .parse::<f64>()
+                                    .ok()
+                                    .and_then(Cost::checked_from_num)
+                                    .unwrap_or_else(|| panic!("Invalid miss cost {:?}", cost))
+                            })
+                            .collect::<Vec<_>>(),
+                    );
+                }
+                "--latency-curve" => {
+                    let path = args.next().expect("--latency-curve expects a file path");
+                    let latency_curve = LatencyCurve::from_csv(&path)
+                        .unwrap_or_else(|e| panic!("Failed to load latency curve {}: {}", path, e));
+                    options.latency_curve = Some((path, latency_curve));
+                }
+                "--threads" => {
+                    let count = args.next().expect("--threads expects a thread count");
+                    options.num_threads = count
+                        .parse()
+                        .ok()
+                        .filter(|&count| count > 0)
+                        .unwrap_or_else(|| panic!("Invalid thread count {:?}", count));
+                }
+                "--num-feeds" => {
+                    let count = args.next().expect("--num-feeds expects a feed count");
+                    options.num_feeds = Some(
+                        count
+                            .parse()
+                            .ok()
+                            .filter(|&count| count > 1)
+                            .unwrap_or_else(|| panic!("Invalid feed count {:?}", count)),
+                    );
+                }
+                "--domain" => {
+                    let name = args.next().expect("--domain expects a domain name");
+                    options.domain = name
+                        .parse()
+                        .unwrap_or_else(|()| panic!("Unknown pair domain {:?}", name));
+                }
+                "--entry-size-ratio" => {
+                    let ratio = args
+                        .next()
+                        .expect("--entry-size-ratio expects a size ratio");
+                    options.entry_size_ratio = Some(
+                        ratio
+                            .parse()
+                            .ok()
+                            .filter(|&ratio: &f32| ratio > 0.0)
+                            .unwrap_or_else(|| panic!("Invalid entry size ratio {:?}", ratio)),
+                    );
+                }
+                "--tlb" => {
+                    let spec = args.next().expect("--tlb expects a page size");
+                    let mut fields = spec.split(',').map(str::trim);
+                    let page_size = fields.next().unwrap();
+                    let mut tlb = Tlb::new(
+                        parse_size(page_size)
+                            .unwrap_or_else(|| panic!("Invalid page size {:?}", page_size)),
+                    );
+                    if let Some(entries) = fields.next() {
+                        tlb.entries = entries
+                            .parse()
+                            .unwrap_or_else(|_| panic!("Invalid TLB entry count {:?}", entries));
+                    }
+                    if let Some(miss_cost) = fields.next() {
+                        tlb.miss_cost = miss_cost
+                            .parse()
+                            .unwrap_or_else(|_| panic!("Invalid TLB miss cost {:?}", miss_cost));
+                    }
+                    options.tlb = Some(tlb);
+                }
+                "--trace" => {
+                    options.trace = Some(args.next().expect("--trace expects a file path"))
+                }
+                "--trace-format" => {
+                    let name = args.next().expect("--trace-format expects a format name");
+                    options.trace_format = name
+                        .parse()
+                        .unwrap_or_else(|()| panic!("Unknown trace format {:?}", name));
+                }
+                "--trace-granularity" => {
+                    let size = args
+                        .next()
+                        .expect("--trace-granularity expects a size in bytes");
+                    options.trace_granularity = parse_size(&size)
+                        .filter(|&size| size > 0)
+                        .unwrap_or_else(|| panic!("Invalid trace granularity {:?}", size));
+                }
+                _ => panic!("Unknown command-line argument {:?}", arg),
+            }
+        }
+        if let Some(miss_costs) = miss_costs {
+            options.cache_hierarchy = options
+                .cache_hierarchy
+                .with_miss_costs(&miss_costs)
+                .unwrap_or_else(|e| panic!("Failed to override miss costs: {}", e));
+        }
+        if let Some(domain_num_feeds) = options.domain.num_feeds() {
+            if matches!(options.num_feeds, Some(num_feeds) if num_feeds != domain_num_feeds) {
+                panic!(
+                    "The {} domain is made of {} feeds",
+                    options.domain.name(),
+                    domain_num_feeds
+                );
+            }
+            options.num_feeds = Some(domain_num_feeds);
+        }
+        if options.entry_size_ratio.is_some()
+            && !matches!(options.domain, PairDomain::Bipartite { .. })
+        {
+            panic!("--entry-size-ratio is only supported in the bipartite domain");
+        }
+
+        // Reject the combinations of cache model features that cannot be
+        // simulated together before running any test
+        let probe_layout = options.feed_layout(
+            options.num_feeds.unwrap_or(2),
+            options.cache_hierarchy.levels[0].capacity / 2,
+        );
+        let probe_model = options.cache_model(probe_layout);
+        if let Err(unsupported) = probe_model
+            .check_features()
+            .and_then(|()| MultiCoreModel::new(probe_model.clone(), options.num_threads).map(drop))
+        {
+            panic!("Unsupported combination of options: {}", unsupported);
+        }
+        options
+    }
+
+    /// Lay out the feed buffers that these options describe in memory, for a
+    /// certain number of feeds and entry size of feed set A
+    fn feed_layout(&self, num_feeds: FeedIdx, entry_size: usize) -> FeedLayout {
+        // Feed buffers are allocated contiguously
+        let layout = FeedLayout::new(num_feeds, entry_size, entry_size);
+        match (self.domain, self.entry_size_ratio) {
+            (PairDomain::Bipartite { num_feeds_a, .. }, Some(ratio)) => {
+                let entry_size_b = ((entry_size as f32 * ratio).round() as usize).max(1);
+                layout.with_second_set(num_feeds_a, entry_size_b)
+            }
+            _ => layout,
+        }
+    }
+
+    /// Build the cache model that these options describe, for a certain
+    /// layout of the feed buffers in memory
+    fn cache_model(&self, layout: FeedLayout) -> CacheModel {
+        let cache_hierarchy = &self.cache_hierarchy;
+        if self.set_associative {
+            // Feed buffers may map into the same cache sets and cause conflict
+            // misses.
+            CacheModel::new_set_associative(cache_hierarchy, layout)
+        } else {
+            CacheModel::new(cache_hierarchy, layout)
+        }
+        .with_replacement_policy(self.replacement_policy.clone())
+        .with_inclusion(self.inclusion)
+        .with_prefetcher(self.prefetcher)
+        .with_memory_parallelism(self.memory_parallelism)
+        .with_latency_curve(
+            self.latency_curve
+                .as_ref()
+                .map(|(_path, latency_curve)| latency_curve.clone()),
+        )
+        .unwrap_or_else(|e| panic!("Failed to apply latency curve: {}", e))
+        .with_output_entries(self.output_size)
+        .with_output_stores(self.output_stores)
+        .with_tlb(self.tlb)
+    }
+}
+
+/// Parse a size in bytes, with an optional K, M or G binary suffix
+fn parse_size(size: &str) -> Option<usize> {
+    let (digits, multiplier) = match size.as_bytes().last()? {
+        b'K' | b'k' => (&size[..size.len() - 1], 1024),
+        b'M' | b'm' => (&size[..size.len() - 1], 1024 * 1024),
+        b'G' | b'g' => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Calibrate the cache model on the host CPU
+///
+/// Usage: cachot calibrate [--max-size <bytes>] [--output <TOML path>]
+///                         [--latency-curve <CSV path>]
+///
+/// The resulting cache hierarchy can be loaded with --cache-file, and the
+/// measured latency curve with --latency-curve.
+///
+fn calibrate(mut args: impl Iterator<Item = String>) {
+    let mut max_size = 256 * 1024 * 1024;
+    let mut output_path = None;
+    let mut curve_path = None;
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "--max-size" => {
+                let size = args.next().expect("--max-size expects a size in bytes");
+                max_size = parse_size(&size)
+                    .unwrap_or_else(|| panic!("Invalid working set size {:?}", size));
+            }
+            "--output" => output_path = Some(args.next().expect("--output expects a file path")),
+            "--latency-curve" => {
+                curve_path = Some(args.next().expect("--latency-curve expects a file path"))
+            }
+            _ => panic!("Unknown calibration argument {:?}", arg),
+        }
+    }
+
+    println!("Measuring memory access latency...");
+    let latency_curve = cache::calibrate::measure_latency_curve(max_size, |bytes, latency| {
+        println!(
+            "- {:>10}: {:.2} ns",
+            cache::hierarchy::format_size(bytes),
+            latency
+        )
+    })
+    .unwrap_or_else(|e| panic!("Failed to measure memory access latency: {}", e));
+    let cache_hierarchy = cache::calibrate::detect_hierarchy(&latency_curve)
+        .unwrap_or_else(|e| panic!("Failed to detect cache levels: {}", e));
+    println!("\n{}", cache_hierarchy);
+
+    if let Some(curve_path) = curve_path {
+        std::fs::write(&curve_path, latency_curve.to_csv())
+            .unwrap_or_else(|e| panic!("Failed to write {}: {}", curve_path, e));
+        println!("Wrote latency curve to {}", curve_path);
+    }
+    let toml = cache_hierarchy.to_toml();
+    if let Some(output_path) = output_path {
+        std::fs::write(&output_path, toml)
+            .unwrap_or_else(|e| panic!("Failed to write {}: {}", output_path, e));
+        println!("Wrote cache hierarchy to {}", output_path);
+    } else {
+        println!("\n{}", toml);
+    }
+}
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some("calibrate") {
+        calibrate(std::env::args().skip(2));
+        return;
+    }
+    let options = Options::from_args();
+    println!(
+        "Simulating {} caches with {} replacement{}\n",
+        if options.set_associative {
+            "set-associative"
+        } else {
+            "fully associative"
+        },
+        options.replacement_policy.name(),
+        if options.prefetcher.is_some() {
+            " and a stride prefetcher"
+        } else {
+            ""
+        }
+    );
+    if let Some(memory_parallelism) = &options.memory_parallelism {
+        println!(
+            "Up to {} cache miss latencies overlap within windows of {} feed pair(s)\n",
+            memory_parallelism.max_misses, memory_parallelism.window
+        );
+    }
+    if let Some((path, latency_curve)) = &options.latency_curve {
+        println!(
+            "Cache miss latencies follow the {}-point latency curve from {}\n",
+            latency_curve.num_points(),
+            path
+        );
+    }
+    if options.num_threads > 1 {
+        println!(
+            "Iterators are also tested on {} threads, whose cores share the last cache level\n",
+            options.num_threads
+        );
+    }
+    if let Some(output_size) = options.output_size {
+        println!(
+            "Each feed pair also accesses an output entry of {}, written with {}\n",
+            cache::hierarchy::format_size(output_size),
+            match options.output_stores {
+                OutputStores::WriteAllocate => "write-allocating stores",
+                OutputStores::NonTemporal => "non-temporal stores",
+            }
+        );
+    }
+    if let Some(tlb) = &options.tlb {
+        println!(
+            "Page translations go through a {}-entry TLB of {} pages, whose misses cost {} L1 miss latencies\n",
+            tlb.entries,
+            cache::hierarchy::format_size(tlb.page_size),
+            tlb.miss_cost
+        );
+    }
+    let (min_capacity, max_capacity) = options
+        .inclusion
+        .effective_capacity(&options.cache_hierarchy);
+    println!(
+        "{}\nLevels are {}, for an effective capacity of {}{}\n",
+        options.cache_hierarchy,
+        options.inclusion.name(),
+        if min_capacity == max_capacity {
+            ""
+        } else {
+            "up to "
+        },
+        cache::hierarchy::format_size(max_capacity)
+    );
+    if let Some(trace_path) = &options.trace {
+        replay_trace(&options, trace_path);
+        return;
+    }
+
     #[rustfmt::skip]
     const TESTED_NUM_FEEDS: &'static [FeedIdx] = &[
         // Minimal useful test (any iteration scheme is optimal with 2 feeds)
@@ -41,18 +506,19 @@ fn main() {
         4,
         // Actual PAON-4 configuration
         8,
-        /* // TODO: What would happen with more feeds?
-        16 */
+        // Configurations of the next instruments
+        16,
+        32,
     ];
-    assert!(*TESTED_NUM_FEEDS.iter().max().unwrap() <= MAX_FEEDS);
+    let tested_num_feeds = match &options.num_feeds {
+        Some(num_feeds) => std::slice::from_ref(num_feeds),
+        None => TESTED_NUM_FEEDS,
+    };
 
+    let cache_hierarchy = &options.cache_hierarchy;
     let mut debug_level = 2;
-    for num_feeds in TESTED_NUM_FEEDS.iter().copied() {
+    for num_feeds in tested_num_feeds.iter().copied() {
         println!("=== Testing with {} feeds ===\n", num_feeds);
-        assert!(
-            num_feeds <= MAX_FEEDS,
-            "Please update MAX_FEEDS for this configuration"
-        );
 
         // The L1 cache must be able to hold data for at least 3 feeds,
         // otherwise every access to a new pair will be a cache miss.
@@ -69,270 +535,491 @@ fn main() {
         // but also with larger chunks of feed data, which are potentially more
         // efficient to process.
         //
+        //
+        // Stack distances do not depend on the L1 capacity, so they are only
+        // computed for the first one and reused for the others.
+        //
+        let mut stack_distances = StackDistances::new();
         for num_l1_entries in 2..num_feeds {
             // Announce and set up the test
             println!("--- Testing L1 capacity of {} feeds ---", num_l1_entries);
-            let entry_size = cache::L1_CAPACITY / num_l1_entries as usize;
-            let mut locality_tester = PairLocalityTester::new(debug_level, entry_size);
+            let entry_size = cache_hierarchy.levels[0].capacity / num_l1_entries as usize;
+
+            let cache_model = options.cache_model(options.feed_layout(num_feeds, entry_size));
+
+            // Costs are normalized by the cost of an L1 miss, which depends on
+            // the entry size, so report it. Total costs are also reported in L1
+            // miss latencies, which can be compared across L1 capacities.
+            println!(
+                "Entries are {}, and each L1 miss costs {:.2} L1 miss latencies ({:.3} per KiB)",
+                cache::hierarchy::format_size(entry_size),
+                cache_model.cost_unit(),
+                cache_model.cost_unit() * 1024.0 / entry_size as f32
+            );
+            if !cache_model.layout().is_uniform() {
+                println!(
+                    "Entries of feed set B are {}",
+                    cache::hierarchy::format_size(cache_model.layout().set_entry_size(1))
+                );
+            }
+            let mut locality_tester = PairLocalityTester::<WideCost>::new(
+                debug_level,
+                cache_model.clone(),
+                options.num_threads,
+                options.domain,
+            )
+            .with_stack_distances(std::mem::take(&mut stack_distances));
+
+            // Test iteration schemes that are adapted to the pair domain
+            match options.domain.rectangle(num_feeds) {
+                Some([xs, ys]) => test_rectangle_iterators(&mut locality_tester, xs, ys),
+                None => test_triangle_iterators(&mut locality_tester, num_feeds),
+            }
+
+            // Tell which iterator got the best results. The brute force search
+            // uses compact costs, which its cumulative costs must fit in.
+            let best_cumulative_cost =
+                locality_tester
+                    .announce_best_iterator()
+                    .and_then(|cumulative_cost| {
+                        cumulative_cost
+                            .iter()
+                            .map(|cost| cost.to_cost().filter(|&cost| cost < Cost::MAX))
+                            .collect::<Option<Vec<_>>>()
+                    });
+            stack_distances = locality_tester.into_stack_distances();
+
+            // Now, let's try to brute-force a better iterator
+            if let Some(mut best_cumulative_cost) = best_cumulative_cost {
+                println!("\nPerforming brute force search for a better path...");
+                brute_force::search_best_path(
+                    num_feeds,
+                    options.domain,
+                    &cache_model,
+                    &mut best_cumulative_cost[..],
+                    Duration::from_secs(60),
+                );
+            } else {
+                println!("\nCache costs are out of brute force search range, skipping it.");
+            }
+
+            debug_level = debug_level.saturating_sub(1);
+            println!();
+        }
+        if !stack_distances.is_empty() {
+            pair_locality::announce_stack_distances(&stack_distances);
+        }
+        println!();
+        debug_level = (num_feeds < 8).into();
+    }
+}
+
+/// Test iteration schemes that go through the y >= x triangle of feed pairs,
+/// which contains the triangular pair domains (pairs outside of the tested
+/// domain are skipped by the locality tester)
+fn test_triangle_iterators(locality_tester: &mut PairLocalityTester<WideCost>, num_feeds: FeedIdx) {
+    // Naive iteration scheme
+    let_gen!(naive, {
+        for feed1 in 0..num_feeds {
+            for feed2 in feed1..num_feeds {
+                yield_!([feed1, feed2]);
+            }
+        }
+    });
+    locality_tester.test_feed_pair_locality("Naive", naive.into_iter());
+
+    // Iteration scheme that goes from top to bottom, and for each row
+    // alternatively goes from right to left and from left to right
+    let_gen!(zigzag, {
+        let mut reverse = true;
+        for feed2 in 0..num_feeds {
+            if reverse {
+                for feed1 in (0..=feed2).rev() {
+                    yield_!([feed1, feed2]);
+                }
+            } else {
+                for feed1 in 0..=feed2 {
+                    yield_!([feed1, feed2]);
+                }
+            }
+            reverse = !reverse;
+        }
+    });
+    locality_tester.test_feed_pair_locality("Zig-zag", zigzag.into_iter());
 
-            // Naive iteration scheme
-            let_gen!(naive, {
-                for feed1 in 0..num_feeds {
-                    for feed2 in feed1..num_feeds {
+    // Variation of the "zig-zag" scheme that also switches the
+    // iteration direction from vertical to horizontal and back whenever
+    // the end of a line is reached
+    let_gen!(zigzag_corner, {
+        let mut reverse = false;
+        let mut offset = 0;
+        while offset < num_feeds - offset {
+            if reverse {
+                for feed1 in ((offset + 1)..(num_feeds - offset)).rev() {
+                    yield_!([feed1, num_feeds - offset - 1]);
+                }
+                for feed2 in (offset..(num_feeds - offset)).rev() {
+                    yield_!([offset, feed2]);
+                }
+            } else {
+                for feed2 in offset..(num_feeds - offset) {
+                    yield_!([offset, feed2]);
+                }
+                for feed1 in (offset + 1)..(num_feeds - offset) {
+                    yield_!([feed1, num_feeds - offset - 1]);
+                }
+            }
+            reverse = !reverse;
+            offset += 1;
+        }
+    });
+    locality_tester.test_feed_pair_locality("Zig-zag corner", zigzag_corner.into_iter());
+
+    // Iteration schemes that gradually shrinks the triangular domain of
+    // radio feed pairs into smaller triangular domains by progressing
+    // in diagonal stripes
+    for stripe_width in 1..num_feeds {
+        // Minimal version, all stripes taken from top-left to bottom-right
+        let_gen!(striped_minimal, {
+            let mut stripe_offset = 0;
+            while stripe_offset < num_feeds {
+                for feed2 in stripe_offset..num_feeds {
+                    let stripe_end = feed2.saturating_sub(stripe_offset);
+                    let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
+                    for feed1 in stripe_start..=stripe_end {
                         yield_!([feed1, feed2]);
                     }
                 }
-            });
-            locality_tester.test_feed_pair_locality("Naive", naive.into_iter());
+                stripe_offset += stripe_width;
+            }
+        });
+        locality_tester.test_feed_pair_locality(
+            &format!("{0}-wide stripes (minimal)", stripe_width),
+            striped_minimal.into_iter(),
+        );
 
-            // Iteration scheme that goes from top to bottom, and for each row
-            // alternatively goes from right to left and from left to right
-            let_gen!(zigzag, {
-                let mut reverse = true;
-                for feed2 in 0..num_feeds {
-                    if reverse {
-                        for feed1 in (0..=feed2).rev() {
+        // Slightly more elaborate iteration order that goes from top
+        // to bottom on the first iteration, from bottom to top on the
+        // second iteration, then back from top to bottom...
+        let_gen!(striped_vertical_zigzag, {
+            let mut stripe_offset = 0;
+            let mut reverse = false;
+            while stripe_offset < num_feeds {
+                if reverse {
+                    for feed2 in (stripe_offset..num_feeds).rev() {
+                        let stripe_end = feed2.saturating_sub(stripe_offset);
+                        let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
+                        for feed1 in (stripe_start..=stripe_end).rev() {
                             yield_!([feed1, feed2]);
                         }
-                    } else {
-                        for feed1 in 0..=feed2 {
+                    }
+                } else {
+                    for feed2 in stripe_offset..num_feeds {
+                        let stripe_end = feed2.saturating_sub(stripe_offset);
+                        let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
+                        for feed1 in stripe_start..=stripe_end {
                             yield_!([feed1, feed2]);
                         }
                     }
-                    reverse = !reverse;
-                }
-            });
-            locality_tester.test_feed_pair_locality("Zig-zag", zigzag.into_iter());
-
-            // Variation of the "zig-zag" scheme that also switches the
-            // iteration direction from vertical to horizontal and back whenever
-            // the end of a line is reached
-            let_gen!(zigzag_corner, {
-                let mut reverse = false;
-                let mut offset = 0;
-                while offset < num_feeds - offset {
-                    if reverse {
-                        for feed1 in ((offset + 1)..(num_feeds - offset)).rev() {
-                            yield_!([feed1, num_feeds - offset - 1]);
-                        }
-                        for feed2 in (offset..(num_feeds - offset)).rev() {
-                            yield_!([offset, feed2]);
-                        }
-                    } else {
-                        for feed2 in offset..(num_feeds - offset) {
-                            yield_!([offset, feed2]);
-                        }
-                        for feed1 in (offset + 1)..(num_feeds - offset) {
-                            yield_!([feed1, num_feeds - offset - 1]);
+                }
+                stripe_offset += stripe_width;
+                reverse = !reverse;
+            }
+        });
+        locality_tester.test_feed_pair_locality(
+            &format!("{0}-wide stripes (vertical zig-zag)", stripe_width),
+            striped_vertical_zigzag.into_iter(),
+        );
+
+        // Further elaboration on top of the "vertical zig-zag" version,
+        // which also goes back and forth in the horizontal direction
+        let_gen!(striped_double_zigzag, {
+            let mut stripe_offset = 0;
+            let mut vertical_reverse = false;
+            while stripe_offset < num_feeds {
+                let mut horizontal_reverse = !vertical_reverse;
+                if vertical_reverse {
+                    for feed2 in (stripe_offset..num_feeds).rev() {
+                        let stripe_end = feed2.saturating_sub(stripe_offset);
+                        let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
+                        if horizontal_reverse {
+                            for feed1 in stripe_start..=stripe_end {
+                                yield_!([feed1, feed2]);
+                            }
+                        } else {
+                            for feed1 in (stripe_start..=stripe_end).rev() {
+                                yield_!([feed1, feed2]);
+                            }
                         }
+                        horizontal_reverse = !horizontal_reverse;
                     }
-                    reverse = !reverse;
-                    offset += 1;
-                }
-            });
-            locality_tester.test_feed_pair_locality("Zig-zag corner", zigzag_corner.into_iter());
-
-            // Iteration schemes that gradually shrinks the triangular domain of
-            // radio feed pairs into smaller triangular domains by progressing
-            // in diagonal stripes
-            for stripe_width in 1..num_feeds {
-                // Minimal version, all stripes taken from top-left to bottom-right
-                let_gen!(striped_minimal, {
-                    let mut stripe_offset = 0;
-                    while stripe_offset < num_feeds {
-                        for feed2 in stripe_offset..num_feeds {
-                            let stripe_end = feed2.saturating_sub(stripe_offset);
-                            let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
+                } else {
+                    for feed2 in stripe_offset..num_feeds {
+                        let stripe_end = feed2.saturating_sub(stripe_offset);
+                        let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
+                        if horizontal_reverse {
+                            for feed1 in (stripe_start..=stripe_end).rev() {
+                                yield_!([feed1, feed2]);
+                            }
+                        } else {
                             for feed1 in stripe_start..=stripe_end {
                                 yield_!([feed1, feed2]);
                             }
                         }
-                        stripe_offset += stripe_width;
+                        horizontal_reverse = !horizontal_reverse;
                     }
-                });
-                locality_tester.test_feed_pair_locality(
-                    &format!("{0}-wide stripes (minimal)", stripe_width),
-                    striped_minimal.into_iter(),
-                );
+                }
+                stripe_offset += stripe_width;
+                vertical_reverse = !vertical_reverse;
+            }
+        });
+        locality_tester.test_feed_pair_locality(
+            &format!("{0}-wide stripes (double zig-zag)", stripe_width),
+            striped_double_zigzag.into_iter(),
+        );
 
-                // Slightly more elaborate iteration order that goes from top
-                // to bottom on the first iteration, from bottom to top on the
-                // second iteration, then back from top to bottom...
-                let_gen!(striped_vertical_zigzag, {
-                    let mut stripe_offset = 0;
-                    let mut reverse = false;
-                    while stripe_offset < num_feeds {
-                        if reverse {
-                            for feed2 in (stripe_offset..num_feeds).rev() {
-                                let stripe_end = feed2.saturating_sub(stripe_offset);
-                                let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
-                                for feed1 in (stripe_start..=stripe_end).rev() {
-                                    yield_!([feed1, feed2]);
-                                }
+        // Final elaboration of the "double zig-zag" version, which
+        // flips the two iteration directions on every stripe
+        let_gen!(striped_mirrored_zigzag, {
+            let mut stripe_offset = 0;
+            let mut primary_reverse = false;
+            while stripe_offset < num_feeds {
+                let mut secondary_reverse = primary_reverse;
+                if primary_reverse {
+                    let feed1_end = num_feeds.saturating_sub(stripe_offset);
+                    for feed1 in (0..feed1_end).rev() {
+                        let stripe_start = (feed1 + stripe_offset).min(num_feeds - 1);
+                        let stripe_end = (stripe_start + stripe_width).min(num_feeds);
+                        if secondary_reverse {
+                            for feed2 in stripe_start..stripe_end {
+                                yield_!([feed1, feed2]);
                             }
                         } else {
-                            for feed2 in stripe_offset..num_feeds {
-                                let stripe_end = feed2.saturating_sub(stripe_offset);
-                                let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
-                                for feed1 in stripe_start..=stripe_end {
-                                    yield_!([feed1, feed2]);
-                                }
+                            for feed2 in (stripe_start..stripe_end).rev() {
+                                yield_!([feed1, feed2]);
                             }
                         }
-                        stripe_offset += stripe_width;
-                        reverse = !reverse;
+                        secondary_reverse = !secondary_reverse;
                     }
-                });
-                locality_tester.test_feed_pair_locality(
-                    &format!("{0}-wide stripes (vertical zig-zag)", stripe_width),
-                    striped_vertical_zigzag.into_iter(),
-                );
+                } else {
+                    for feed2 in stripe_offset..num_feeds {
+                        let stripe_end = feed2.saturating_sub(stripe_offset);
+                        let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
+                        if secondary_reverse {
+                            for feed1 in (stripe_start..=stripe_end).rev() {
+                                yield_!([feed1, feed2]);
+                            }
+                        } else {
+                            for feed1 in stripe_start..=stripe_end {
+                                yield_!([feed1, feed2]);
+                            }
+                        }
+                        secondary_reverse = !secondary_reverse;
+                    }
+                }
+                stripe_offset += stripe_width;
+                primary_reverse = !primary_reverse;
+            }
+        });
+        locality_tester.test_feed_pair_locality(
+            &format!("{0}-wide stripes (mirrored zig-zag)", stripe_width),
+            striped_mirrored_zigzag.into_iter(),
+        );
+    }
+
+    // Block-wise iteration scheme
+    for block_size in 2..num_feeds {
+        let_gen!(blocked_basic, {
+            for feed1_block in (0..num_feeds).step_by(block_size.into()) {
+                for feed2_block in (feed1_block..num_feeds).step_by(block_size.into()) {
+                    for feed1 in feed1_block..(feed1_block + block_size).min(num_feeds) {
+                        for feed2 in
+                            feed1.max(feed2_block)..(feed2_block + block_size).min(num_feeds)
+                        {
+                            yield_!([feed1, feed2]);
+                        }
+                    }
+                }
+            }
+        });
+        locality_tester.test_feed_pair_locality(
+            &format!("{0}x{0} blocks", block_size),
+            blocked_basic.into_iter(),
+        );
+    }
+
+    // Space-filling curves cover a square whose side is a power of two,
+    // which contains the triangle. Pairs outside of the domain are skipped
+    // by the locality tester.
+    let curve_len = (num_feeds as usize).next_power_of_two().pow(2);
+
+    // Morton curve ("Z order") iteration
+    let morton = morton::iter_2d().take(curve_len);
+    locality_tester.test_feed_pair_locality("Morton curve", morton);
+
+    // Hilbert curve iteration (the largest curve has CurveIdx::MAX + 1
+    // points, hence the inclusive range)
+    let hilbert = (0..=(curve_len - 1) as CurveIdx).map(hilbert::decode_2d);
+    locality_tester.test_feed_pair_locality("Hilbert curve", hilbert);
+}
 
-                // Further elaboration on top of the "vertical zig-zag" version,
-                // which also goes back and forth in the horizontal direction
-                let_gen!(striped_double_zigzag, {
-                    let mut stripe_offset = 0;
-                    let mut vertical_reverse = false;
-                    while stripe_offset < num_feeds {
-                        let mut horizontal_reverse = !vertical_reverse;
+/// Test iteration schemes that natively go through a rectangle of feed pairs,
+/// whose x coordinates span `xs` and whose y coordinates span `ys`
+fn test_rectangle_iterators(
+    locality_tester: &mut PairLocalityTester<WideCost>,
+    xs: Range<FeedIdx>,
+    ys: Range<FeedIdx>,
+) {
+    let (x_start, x_end, y_start, y_end) = (xs.start, xs.end, ys.start, ys.end);
+
+    // Naive iteration scheme
+    let_gen!(naive, {
+        for x in x_start..x_end {
+            for y in y_start..y_end {
+                yield_!([x, y]);
+            }
+        }
+    });
+    locality_tester.test_feed_pair_locality("Naive", naive.into_iter());
+
+    // Iteration scheme that goes through rows alternatively from left to
+    // right and from right to left
+    let_gen!(zigzag, {
+        let mut reverse = false;
+        for x in x_start..x_end {
+            if reverse {
+                for y in (y_start..y_end).rev() {
+                    yield_!([x, y]);
+                }
+            } else {
+                for y in y_start..y_end {
+                    yield_!([x, y]);
+                }
+            }
+            reverse = !reverse;
+        }
+    });
+    locality_tester.test_feed_pair_locality("Zig-zag", zigzag.into_iter());
+
+    // Iteration scheme that goes through stripes of several rows, column by
+    // column, zig-zagging both across columns and within each of them
+    for stripe_width in 2..(x_end - x_start) {
+        let_gen!(striped, {
+            let mut vertical_reverse = false;
+            let mut horizontal_reverse = false;
+            for stripe_start in (x_start..x_end).step_by(stripe_width.into()) {
+                let stripe_end = (stripe_start + stripe_width).min(x_end);
+                if horizontal_reverse {
+                    for y in (y_start..y_end).rev() {
                         if vertical_reverse {
-                            for feed2 in (stripe_offset..num_feeds).rev() {
-                                let stripe_end = feed2.saturating_sub(stripe_offset);
-                                let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
-                                if horizontal_reverse {
-                                    for feed1 in stripe_start..=stripe_end {
-                                        yield_!([feed1, feed2]);
-                                    }
-                                } else {
-                                    for feed1 in (stripe_start..=stripe_end).rev() {
-                                        yield_!([feed1, feed2]);
-                                    }
-                                }
-                                horizontal_reverse = !horizontal_reverse;
+                            for x in (stripe_start..stripe_end).rev() {
+                                yield_!([x, y]);
                             }
                         } else {
-                            for feed2 in stripe_offset..num_feeds {
-                                let stripe_end = feed2.saturating_sub(stripe_offset);
-                                let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
-                                if horizontal_reverse {
-                                    for feed1 in (stripe_start..=stripe_end).rev() {
-                                        yield_!([feed1, feed2]);
-                                    }
-                                } else {
-                                    for feed1 in stripe_start..=stripe_end {
-                                        yield_!([feed1, feed2]);
-                                    }
-                                }
-                                horizontal_reverse = !horizontal_reverse;
+                            for x in stripe_start..stripe_end {
+                                yield_!([x, y]);
                             }
                         }
-                        stripe_offset += stripe_width;
                         vertical_reverse = !vertical_reverse;
                     }
-                });
-                locality_tester.test_feed_pair_locality(
-                    &format!("{0}-wide stripes (double zig-zag)", stripe_width),
-                    striped_double_zigzag.into_iter(),
-                );
-
-                // Final elaboration of the "double zig-zag" version, which
-                // flips the two iteration directions on every stripe
-                let_gen!(striped_mirrored_zigzag, {
-                    let mut stripe_offset = 0;
-                    let mut primary_reverse = false;
-                    while stripe_offset < num_feeds {
-                        let mut secondary_reverse = primary_reverse;
-                        if primary_reverse {
-                            let feed1_end = num_feeds.saturating_sub(stripe_offset);
-                            for feed1 in (0..feed1_end).rev() {
-                                let stripe_start = (feed1 + stripe_offset).min(num_feeds - 1);
-                                let stripe_end = (stripe_start + stripe_width).min(num_feeds);
-                                if secondary_reverse {
-                                    for feed2 in stripe_start..stripe_end {
-                                        yield_!([feed1, feed2]);
-                                    }
-                                } else {
-                                    for feed2 in (stripe_start..stripe_end).rev() {
-                                        yield_!([feed1, feed2]);
-                                    }
-                                }
-                                secondary_reverse = !secondary_reverse;
+                } else {
+                    for y in y_start..y_end {
+                        if vertical_reverse {
+                            for x in (stripe_start..stripe_end).rev() {
+                                yield_!([x, y]);
                             }
                         } else {
-                            for feed2 in stripe_offset..num_feeds {
-                                let stripe_end = feed2.saturating_sub(stripe_offset);
-                                let stripe_start = stripe_end.saturating_sub(stripe_width - 1);
-                                if secondary_reverse {
-                                    for feed1 in (stripe_start..=stripe_end).rev() {
-                                        yield_!([feed1, feed2]);
-                                    }
-                                } else {
-                                    for feed1 in stripe_start..=stripe_end {
-                                        yield_!([feed1, feed2]);
-                                    }
-                                }
-                                secondary_reverse = !secondary_reverse;
+                            for x in stripe_start..stripe_end {
+                                yield_!([x, y]);
                             }
                         }
-                        stripe_offset += stripe_width;
-                        primary_reverse = !primary_reverse;
+                        vertical_reverse = !vertical_reverse;
                     }
-                });
-                locality_tester.test_feed_pair_locality(
-                    &format!("{0}-wide stripes (mirrored zig-zag)", stripe_width),
-                    striped_mirrored_zigzag.into_iter(),
-                );
+                }
+                horizontal_reverse = !horizontal_reverse;
             }
+        });
+        locality_tester.test_feed_pair_locality(
+            &format!("{0}-wide stripes", stripe_width),
+            striped.into_iter(),
+        );
+    }
 
-            // Block-wise iteration scheme
-            for block_size in 2..num_feeds {
-                let_gen!(blocked_basic, {
-                    for feed1_block in (0..num_feeds).step_by(block_size.into()) {
-                        for feed2_block in (feed1_block..num_feeds).step_by(block_size.into()) {
-                            for feed1 in feed1_block..(feed1_block + block_size).min(num_feeds) {
-                                for feed2 in feed1.max(feed2_block)
-                                    ..(feed2_block + block_size).min(num_feeds)
-                                {
-                                    yield_!([feed1, feed2]);
-                                }
-                            }
+    // Block-wise iteration scheme
+    for block_size in 2..(x_end - x_start).max(y_end - y_start) {
+        let_gen!(blocked_basic, {
+            for x_block in (x_start..x_end).step_by(block_size.into()) {
+                for y_block in (y_start..y_end).step_by(block_size.into()) {
+                    for x in x_block..(x_block + block_size).min(x_end) {
+                        for y in y_block..(y_block + block_size).min(y_end) {
+                            yield_!([x, y]);
                         }
                     }
-                });
-                locality_tester.test_feed_pair_locality(
-                    &format!("{0}x{0} blocks", block_size),
-                    blocked_basic.into_iter(),
-                );
+                }
             }
+        });
+        locality_tester.test_feed_pair_locality(
+            &format!("{0}x{0} blocks", block_size),
+            blocked_basic.into_iter(),
+        );
+    }
 
-            // Morton curve ("Z order") iteration
-            let morton = morton::iter_2d()
-                .take(num_feeds as usize * num_feeds as usize)
-                .filter(|[feed1, feed2]| feed2 >= feed1);
-            locality_tester.test_feed_pair_locality("Morton curve", morton);
+    // Space-filling curves cover a square whose side is a power of two, and
+    // which starts at the rectangle's corner. Points outside of the
+    // rectangle are skipped.
+    let side = ((x_end - x_start).max(y_end - y_start) as usize).next_power_of_two();
+    let curve_len = side.pow(2);
+    let to_rectangle = move |[x, y]: [FeedIdx; 2]| {
+        Some([x_start.checked_add(x)?, y_start.checked_add(y)?])
+            .filter(|&[x, y]| x < x_end && y < y_end)
+    };
 
-            // Hilbert curve iteration
-            let hilbert = (0..(num_feeds as CurveIdx * num_feeds as CurveIdx))
-                .map(hilbert::decode_2d)
-                .filter(|[feed1, feed2]| feed2 >= feed1);
-            locality_tester.test_feed_pair_locality("Hilbert curve", hilbert);
+    // Morton curve ("Z order") iteration
+    let morton = morton::iter_2d().take(curve_len).filter_map(to_rectangle);
+    locality_tester.test_feed_pair_locality("Morton curve", morton);
 
-            // Tell which iterator got the best results
-            let mut best_cumulative_cost = locality_tester.announce_best_iterator().to_owned();
+    // Hilbert curve iteration (the largest curve has CurveIdx::MAX + 1
+    // points, hence the inclusive range)
+    let hilbert = (0..=(curve_len - 1) as CurveIdx)
+        .map(hilbert::decode_2d)
+        .filter_map(to_rectangle);
+    locality_tester.test_feed_pair_locality("Hilbert curve", hilbert);
+}
 
-            // Now, let's try to brute-force a better iterator
-            println!("\nPerforming brute force search for a better path...");
-            brute_force::search_best_path(
-                num_feeds,
-                entry_size,
-                &mut best_cumulative_cost[..],
-                Duration::from_secs(60),
-            );
+/// Replay a memory access trace through the cache model
+fn replay_trace(options: &Options, trace_path: &str) {
+    assert!(
+        options.output_size.is_none(),
+        "Output entries cannot be modeled when replaying a trace"
+    );
+    let trace = Trace::from_file(trace_path, options.trace_format, options.trace_granularity)
+        .unwrap_or_else(|e| panic!("Failed to load trace {}: {}", trace_path, e));
+    println!(
+        "Replaying {} reads and {} writes from {} ({} other records ignored), which access {} entries of {}",
+        trace.num_reads(),
+        trace.num_writes(),
+        trace_path,
+        trace.num_ignored(),
+        trace.num_entries(),
+        cache::hierarchy::format_size(options.trace_granularity)
+    );
+    let cache_model = options.cache_model(FeedLayout::new(
+        trace.num_entries() as FeedIdx,
+        options.trace_granularity,
+        options.trace_granularity,
+    ));
+    println!(
+        "Each L1 miss costs {:.2} L1 miss latencies\n",
+        cache_model.cost_unit()
+    );
 
-            debug_level = debug_level.saturating_sub(1);
-            println!();
-        }
-        println!();
-        debug_level = (num_feeds < 8).into();
-    }
+    let trace_name = std::path::Path::new(trace_path)
+        .file_name()
+        .map_or(trace_path.into(), |name| name.to_string_lossy());
+    let mut locality_tester =
+        PairLocalityTester::<WideCost>::new(0, cache_model, options.num_threads, options.domain);
+    locality_tester.test_trace_locality(&trace_name, &trace);
+    locality_tester.announce_best_iterator();
+    println!();
+    pair_locality::announce_stack_distances(&locality_tester.into_stack_distances());
 }
@@ -0,0 +1,217 @@
+//! Sets of feed pairs that iteration schemes must go through
+
+use crate::{brute_force::FeedPair, capacity, FeedIdx};
+use std::{ops::Range, str::FromStr};
+
+/// Set of feed pairs `[x, y]` that an iteration scheme must go through, where
+/// x and y are feeds between 0 and the number of feeds
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PairDomain {
+    /// Pairs where y >= x, which includes autocorrelations `[i, i]`
+    Triangle,
+
+    /// Pairs where y > x, for when autocorrelations are computed separately
+    StrictTriangle,
+
+    /// All ordered pairs, for asymmetric kernels where `[a, b]` and `[b, a]`
+    /// are distinct jobs
+    Square,
+
+    /// Pairs made of one feed of a set A and one feed of a set B, which form a
+    /// rectangle. The feeds of set A come first, so x belongs to set A and y
+    /// belongs to set B.
+    Bipartite {
+        /// Number of feeds in set A
+        num_feeds_a: FeedIdx,
+
+        /// Number of feeds in set B
+        num_feeds_b: FeedIdx,
+    },
+}
+//
+impl PairDomain {
+    /// Name of this domain, for reporting purposes
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Triangle => "triangle",
+            Self::StrictTriangle => "strict-triangle",
+            Self::Square => "square",
+            Self::Bipartite { .. } => "bipartite",
+        }
+    }
+
+    /// Number of feeds that the domain is made of, if it is fixed
+    pub fn num_feeds(self) -> Option<FeedIdx> {
+        match self {
+            Self::Triangle | Self::StrictTriangle | Self::Square => None,
+            Self::Bipartite {
+                num_feeds_a,
+                num_feeds_b,
+            } => Some(num_feeds_a + num_feeds_b),
+        }
+    }
+
+    /// Tell whether a feed pair belongs to the domain
+    pub fn contains(self, num_feeds: FeedIdx, &[x, y]: &FeedPair) -> bool {
+        x < self.end_x(num_feeds) && y < num_feeds && y >= self.min_y(x)
+    }
+
+    /// End (excluded) of the x coordinates of the domain's pairs
+    pub fn end_x(self, num_feeds: FeedIdx) -> FeedIdx {
+        match self {
+            Self::Triangle | Self::StrictTriangle | Self::Square => num_feeds,
+            Self::Bipartite { num_feeds_a, .. } => num_feeds_a,
+        }
+    }
+
+    /// Smallest y coordinate of the domain's pairs whose x coordinate is `x`
+    //
+    // NOTE: This operation is hot and must be fast
+    //
+    #[inline(always)]
+    pub fn min_y(self, x: FeedIdx) -> FeedIdx {
+        match self {
+            Self::Triangle => x,
+            Self::StrictTriangle => x + 1,
+            Self::Square => 0,
+            Self::Bipartite { num_feeds_a, .. } => num_feeds_a,
+        }
+    }
+
+    /// Number of feed pairs in the domain
+    pub fn num_pairs(self, num_feeds: FeedIdx) -> usize {
+        match self {
+            Self::Triangle => capacity::num_unordered_pairs(num_feeds),
+            Self::StrictTriangle => capacity::num_unordered_pairs(num_feeds) - num_feeds as usize,
+            Self::Square => num_feeds as usize * num_feeds as usize,
+            Self::Bipartite {
+                num_feeds_a,
+                num_feeds_b,
+            } => num_feeds_a as usize * num_feeds_b as usize,
+        }
+    }
+
+    /// Tell whether the domain is its own image through the symmetry that maps
+    /// feed pair (x, y) to (num_feeds-1-y, num_feeds-1-x)
+    ///
+    /// This is the case of every domain, except rectangles which are not
+    /// squares, as the symmetry swaps the roles of sets A and B.
+    ///
+    pub fn is_mirror_symmetric(self) -> bool {
+        match self {
+            Self::Triangle | Self::StrictTriangle | Self::Square => true,
+            Self::Bipartite {
+                num_feeds_a,
+                num_feeds_b,
+            } => num_feeds_a == num_feeds_b,
+        }
+    }
+
+    /// Ranges of the x and y coordinates of the domain's pairs, if it is a
+    /// rectangle rather than a triangle
+    ///
+    /// Rectangular domains are best iterated natively. For example, a square
+    /// could be seen as a triangle where each pair `[x, y]` is followed by its
+    /// mirror `[y, x]`, but then every iteration scheme would only be a
+    /// triangle scheme whose results are doubled.
+    ///
+    pub fn rectangle(self, num_feeds: FeedIdx) -> Option<[Range<FeedIdx>; 2]> {
+        match self {
+            Self::Triangle | Self::StrictTriangle => None,
+            Self::Square => Some([0..num_feeds, 0..num_feeds]),
+            Self::Bipartite { num_feeds_a, .. } => Some([0..num_feeds_a, num_feeds_a..num_feeds]),
+        }
+    }
+}
+//
+impl FromStr for PairDomain {
+    type Err = ();
+
+    /// Parse a domain name, or "bipartite:<N>x<M>" for a bipartite domain with
+    /// N feeds in set A and M feeds in set B
+    fn from_str(s: &str) -> Result<Self, ()> {
+        if let Some(sizes) = s.strip_prefix("bipartite:") {
+            let (num_feeds_a, num_feeds_b) = sizes.split_once('x').ok_or(())?;
+            let parse_size = |size: &str| {
+                size.parse::<FeedIdx>()
+                    .ok()
+                    .filter(|&size| size > 0)
+                    .ok_or(())
+            };
+            let (num_feeds_a, num_feeds_b) = (parse_size(num_feeds_a)?, parse_size(num_feeds_b)?);
+            num_feeds_a.checked_add(num_feeds_b).ok_or(())?;
+            return Ok(Self::Bipartite {
+                num_feeds_a,
+                num_feeds_b,
+            });
+        }
+        [Self::Triangle, Self::StrictTriangle, Self::Square]
+            .iter()
+            .copied()
+            .find(|domain| domain.name() == s)
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_domains() {
+        assert_eq!("triangle".parse(), Ok(PairDomain::Triangle));
+        assert_eq!("strict-triangle".parse(), Ok(PairDomain::StrictTriangle));
+        assert_eq!("square".parse(), Ok(PairDomain::Square));
+        assert_eq!(
+            "bipartite:3x5".parse(),
+            Ok(PairDomain::Bipartite {
+                num_feeds_a: 3,
+                num_feeds_b: 5
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_domains() {
+        for name in [
+            "",
+            "Triangle",
+            "rectangle",
+            "bipartite",
+            "bipartite:",
+            "bipartite:3",
+            "bipartite:3x",
+            "bipartite:0x5",
+            "bipartite:3x-5",
+            "bipartite:3x5x7",
+        ] {
+            assert_eq!(name.parse::<PairDomain>(), Err(()), "{:?}", name);
+        }
+        let max = FeedIdx::MAX;
+        assert_eq!(
+            format!("bipartite:{}x1", max).parse::<PairDomain>(),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn rectangles_hold_the_domain() {
+        let num_feeds = 8;
+        for domain in [
+            PairDomain::Square,
+            PairDomain::Bipartite {
+                num_feeds_a: 3,
+                num_feeds_b: 5,
+            },
+        ] {
+            let [xs, ys] = domain.rectangle(num_feeds).unwrap();
+            let pairs = xs
+                .flat_map(|x| ys.clone().map(move |y| [x, y]))
+                .collect::<Vec<_>>();
+            assert_eq!(pairs.len(), domain.num_pairs(num_feeds));
+            assert!(pairs.iter().all(|pair| domain.contains(num_feeds, pair)));
+        }
+        assert_eq!(PairDomain::Triangle.rectangle(num_feeds), None);
+        assert_eq!(PairDomain::StrictTriangle.rectangle(num_feeds), None);
+    }
+}
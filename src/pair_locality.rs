@@ -1,35 +1,102 @@
 //! Mechanism for testing the cache locality of pair iterators
 
 use crate::{
-    cache::{self, CacheModel, L1_MISS_COST, NEW_ENTRY_COST},
+    cache::{
+        self, AccessCost, CacheModel, CacheStats, CostAccumulator, CostOverflow, MultiCoreModel,
+        StackDistanceHistogram, Trace, L1_MISS_COST, NEW_ENTRY_COST,
+    },
+    capacity::LargestCapacity,
+    pair_domain::PairDomain,
     FeedIdx,
 };
-use num_traits::identities::Zero;
+
+/// Number of steps beyond which the cumulative cost of the best iterator is
+/// summarized instead of printed in full, as for long memory access traces
+const MAX_PRINTED_STEPS: usize = 1024;
 
 /// Test harness for evaluating the locality of several feed pair iterators and
 /// picking the best of them.
-pub struct PairLocalityTester {
+///
+/// Cache costs are accumulated over each iterator's path using the `C` type,
+/// which should have enough range for the longest paths. Iterators whose cost
+/// overflows this type are reported and ignored.
+///
+pub struct PairLocalityTester<C: CostAccumulator> {
     debug_level: usize,
     cache_model: CacheModel,
-    best_iterator: Option<(String, Box<[cache::Cost]>)>,
+    multi_core_model: Option<MultiCoreModel>,
+    domain: PairDomain,
+    best_iterator: Option<(String, Box<[C]>, CacheStats)>,
+    stack_distances: StackDistances,
 }
+
+/// Stack distance histograms of the iterators tested so far, by name
+pub type StackDistances = Vec<(String, StackDistanceHistogram)>;
 //
-impl PairLocalityTester {
+impl<C: CostAccumulator> PairLocalityTester<C> {
     /// Build the test harness
-    pub fn new(debug_level: usize, entry_size: usize) -> Self {
+    ///
+    /// If `num_threads` is more than 1, every iterator is also tested on that
+    /// many threads, which process consecutive chunks of the feed pair path.
+    ///
+    /// Feed pair iterators must go through every pair of `domain`.
+    ///
+    pub fn new(
+        debug_level: usize,
+        cache_model: CacheModel,
+        num_threads: usize,
+        domain: PairDomain,
+    ) -> Self {
+        let multi_core_model = if num_threads > 1 {
+            Some(
+                MultiCoreModel::new(cache_model.clone(), num_threads)
+                    .unwrap_or_else(|e| panic!("Unsupported multi-core model: {}", e)),
+            )
+        } else {
+            None
+        };
         if debug_level == 0 {
-            println!("┌──────────────────────────────────────────┬──────────────────┬────────────────────┬───────────────┐");
-            println!("│ Iterator name                            │ Total cache cost │ w/o first accesses │ Per feed load │");
-            println!("├──────────────────────────────────────────┼──────────────────┼────────────────────┼───────────────┤");
+            if multi_core_model.is_some() {
+                println!("┌──────────────────────────────────────────┬──────────────────┬──────────────┬────────────────────┬────────────┬───────────────┬─────────────────┬───────────┐");
+                println!("│ Iterator name                            │ Total cache cost │ L1 latencies │ w/o first accesses │ Write cost │ Per feed load │ Multi-core cost │ Imbalance │");
+                println!("├──────────────────────────────────────────┼──────────────────┼──────────────┼────────────────────┼────────────┼───────────────┼─────────────────┼───────────┤");
+            } else {
+                println!("┌──────────────────────────────────────────┬──────────────────┬──────────────┬────────────────────┬────────────┬───────────────┐");
+                println!("│ Iterator name                            │ Total cache cost │ L1 latencies │ w/o first accesses │ Write cost │ Per feed load │");
+                println!("├──────────────────────────────────────────┼──────────────────┼──────────────┼────────────────────┼────────────┼───────────────┤");
+            }
         }
         Self {
             debug_level,
-            cache_model: CacheModel::new(entry_size),
+            cache_model,
+            multi_core_model,
+            domain,
             best_iterator: None,
+            stack_distances: Vec::new(),
         }
     }
 
+    /// Reuse the stack distance histograms of a previous test harness
+    ///
+    /// Stack distances do not depend on the cache model, so when the same
+    /// iterators are tested against several cache models, their histograms
+    /// only need to be computed once.
+    ///
+    pub fn with_stack_distances(mut self, stack_distances: StackDistances) -> Self {
+        self.stack_distances = stack_distances;
+        self
+    }
+
+    /// Extract the stack distance histograms of the iterators tested so far
+    pub fn into_stack_distances(self) -> StackDistances {
+        self.stack_distances
+    }
+
     /// Test the locality of one feed pair iterator, with diagnostics
+    ///
+    /// Pairs outside of the tester's domain are skipped, so that iterators
+    /// designed for a larger domain can be used on a smaller one.
+    ///
     pub fn test_feed_pair_locality(
         &mut self,
         name: &str,
@@ -38,99 +105,426 @@ impl PairLocalityTester {
         if self.debug_level > 0 {
             println!("\nTesting feed pair iterator \"{}\"...", name);
         }
-        let mut cache_sim = self.cache_model.start_simulation();
-        let mut total_cost = cache::Cost::zero();
-        let mut new_entries_cost = cache::Cost::zero();
-        let mut cumulative_cost = Vec::new();
-        let mut feed_load_count = 0;
-        for feed_pair in feed_pair_iterator {
+        let (domain, num_feeds) = (self.domain, self.cache_model.num_feeds());
+        let feed_pairs = feed_pair_iterator
+            .filter(|pair| domain.contains(num_feeds, pair))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            feed_pairs.len(),
+            domain.num_pairs(num_feeds),
+            "Iterator \"{}\" does not go through the whole {} domain",
+            name,
+            domain.name()
+        );
+        self.test_locality(name, &feed_pairs, true);
+    }
+
+    /// Test the locality of a memory access trace, with diagnostics
+    ///
+    /// Each access of the trace is simulated on its own, without the output
+    /// entry accesses that follow each feed pair.
+    ///
+    pub fn test_trace_locality(&mut self, name: &str, trace: &Trace) {
+        if self.debug_level > 0 {
+            println!("\nReplaying memory access trace \"{}\"...", name);
+        }
+        let accesses = trace
+            .entries()
+            .iter()
+            .map(std::slice::from_ref)
+            .collect::<Vec<_>>();
+        self.test_locality(name, &accesses, false);
+    }
+
+    /// Test the locality of a sequence of steps, each of which accesses some
+    /// feed entries, followed by an output entry if `output_accesses` is set
+    fn test_locality<S: AsRef<[FeedIdx]>>(
+        &mut self,
+        name: &str,
+        steps: &[S],
+        output_accesses: bool,
+    ) {
+        let histogram_idx = self
+            .stack_distances
+            .iter()
+            .position(|(known_name, _)| known_name == name)
+            .unwrap_or_else(|| {
+                self.stack_distances.push((
+                    name.to_owned(),
+                    StackDistanceHistogram::new(
+                        steps.iter().flat_map(|step| step.as_ref().iter().copied()),
+                    ),
+                ));
+                self.stack_distances.len() - 1
+            });
+        if self.debug_level > 0 {
+            let stack_distances = &self.stack_distances[histogram_idx].1;
+            println!(
+                "- Stack distance histogram is {:?}, with {} cold accesses",
+                stack_distances.counts(),
+                stack_distances.cold_accesses()
+            );
+            println!(
+                "- A fully associative LRU cache of {} feeds would miss {} times, including cold misses",
+                self.cache_model.max_l1_entries(),
+                stack_distances.misses(self.cache_model.max_l1_entries())
+            );
+        }
+        let costs = self
+            .evaluate_path(steps, output_accesses)
+            .and_then(|path_costs| {
+                let multi_core_costs = self
+                    .multi_core_model
+                    .as_ref()
+                    .map(|multi_core_model| {
+                        self.test_multi_core_locality(multi_core_model, steps, output_accesses)
+                    })
+                    .transpose()?;
+                Ok((path_costs, multi_core_costs))
+            });
+        let (path_costs, multi_core_costs) = match costs {
+            Ok(costs) => costs,
+            Err(error) => {
+                self.report_overflow(name, error);
+                return;
+            }
+        };
+        let PathCosts {
+            total: total_cost,
+            new_entries: new_entries_cost,
+            write: write_cost,
+            cumulative: cumulative_cost,
+            stats,
+        } = path_costs;
+        let feed_load_count = steps.iter().map(|step| step.as_ref().len()).sum::<usize>();
+        let total_latencies = self.latencies(total_cost);
+        match self.debug_level {
+            0 => {
+                print!(
+                    "│ {:<40} │ {:<16} │ {:<12.1} │ {:<18} │ {:<10} │ {:<13.2} │",
+                    name,
+                    total_cost,
+                    total_latencies,
+                    total_cost - new_entries_cost,
+                    write_cost,
+                    (total_cost - new_entries_cost).to_f32() / (feed_load_count as f32)
+                );
+                if let Some((thread_costs, multi_core_cost)) = &multi_core_costs {
+                    print!(
+                        " {:<15} │ {:<9.2} │",
+                        multi_core_cost,
+                        load_imbalance(thread_costs, *multi_core_cost)
+                    );
+                }
+                println!();
+            }
+            _ => println!(
+                "- Total cache cost of this iterator is {} ({:.1} L1 miss latencies), {} w/o first accesses, {} from writes, {:.2} per feed load",
+                total_cost,
+                total_latencies,
+                total_cost - new_entries_cost,
+                write_cost,
+                (total_cost - new_entries_cost).to_f32() / (feed_load_count as f32)
+            ),
+        }
+        if self.debug_level > 0 {
+            println!("- Cache access statistics are: {}", stats);
+        }
+        if let Some((thread_costs, multi_core_cost)) =
+            multi_core_costs.as_ref().filter(|_| self.debug_level > 0)
+        {
+            println!(
+                "- On {} threads, total cache cost is {}, {:?} per thread, for a load imbalance of {:.2}",
+                thread_costs.len(),
+                multi_core_cost,
+                thread_costs,
+                load_imbalance(thread_costs, *multi_core_cost)
+            );
+        }
+        let is_best = self
+            .best_iterator
+            .as_ref()
+            .map(|(_name, best_cumulative_cost, _stats)| {
+                total_cost < *best_cumulative_cost.last().unwrap()
+            })
+            .unwrap_or(true);
+        if is_best {
+            self.best_iterator = Some((name.to_owned(), cumulative_cost.into(), stats));
+        }
+    }
+
+    /// Simulate the cache accesses of a path, as in `test_locality()`, and
+    /// accumulate their cost, with diagnostics
+    fn evaluate_path<S: AsRef<[FeedIdx]>>(
+        &self,
+        steps: &[S],
+        output_accesses: bool,
+    ) -> Result<PathCosts<C>, CostOverflow> {
+        let mut cache_sim = self.cache_model.start_simulation::<LargestCapacity>();
+        cache_sim.enable_stats(&self.cache_model);
+        cache_sim.enable_recency_index(&self.cache_model);
+        let mut total_cost = C::ZERO;
+        let mut new_entries_cost = C::ZERO;
+        let mut write_cost = C::ZERO;
+        let mut cumulative_cost = Vec::with_capacity(steps.len());
+        let (step_name, step_kind) = if output_accesses {
+            ("feed pair", "pair")
+        } else {
+            ("entries", "access")
+        };
+        for step in steps.iter() {
+            let feed_pair = step.as_ref();
             if self.debug_level >= 2 {
-                println!("- Accessing feed pair {:?}...", feed_pair)
+                println!("- Accessing {} {:?}...", step_name, feed_pair)
             }
-            let mut pair_cost = cache::Cost::zero();
-            let mut pair_entries_cost = cache::Cost::zero();
+            let mut pair_cost = C::ZERO;
+            let mut pair_write_cost = C::ZERO;
+            let mut pair_entries_cost = C::ZERO;
             for feed in feed_pair.iter().copied() {
                 let prev_accessed_entries = cache_sim.num_accessed_entries();
-                let feed_cost = cache_sim.simulate_access(&self.cache_model, feed);
+                let feed_cost = cache_sim.simulate_access(&self.cache_model, feed)?;
                 let is_new_entry = cache_sim.num_accessed_entries() != prev_accessed_entries;
                 let new_entry_str = if is_new_entry { " (first access)" } else { "" };
+                let feed_total_cost = accumulated_cost::<C>(feed_cost)?;
                 if self.debug_level >= 2 {
                     println!(
                         "  * Accessed feed {} for cache cost {}{}",
-                        feed, feed_cost, new_entry_str
+                        feed, feed_total_cost, new_entry_str
                     )
                 }
-                pair_cost += feed_cost;
-                pair_entries_cost +=
-                    cache::Cost::from_num(is_new_entry as u8) * NEW_ENTRY_COST / L1_MISS_COST;
+                pair_cost.add_assign_checked(feed_total_cost)?;
+                pair_write_cost.add_cost(feed_cost.write)?;
+                pair_entries_cost.add_cost(
+                    cache::Cost::from_num(is_new_entry as u8) * NEW_ENTRY_COST / L1_MISS_COST,
+                )?;
             }
-            let new_entries_str = if pair_entries_cost != 0.0 {
-                format!(" ({} from first accesses)", pair_entries_cost)
+            let output_cost = if output_accesses {
+                cache_sim.simulate_output_access(&self.cache_model)?
             } else {
+                cache::AccessCost::default()
+            };
+            let output_total_cost = accumulated_cost::<C>(output_cost)?;
+            if self.debug_level >= 2 && output_total_cost != C::ZERO {
+                println!(
+                    "  * Accessed output entry for cache cost {}",
+                    output_total_cost
+                )
+            }
+            pair_cost.add_assign_checked(output_total_cost)?;
+            pair_write_cost.add_cost(output_cost.write)?;
+            let mut cost_details = Vec::new();
+            if pair_entries_cost != C::ZERO {
+                cost_details.push(format!("{} from first accesses", pair_entries_cost));
+            }
+            if pair_write_cost != C::ZERO {
+                cost_details.push(format!("{} from writes", pair_write_cost));
+            }
+            let new_entries_str = if cost_details.is_empty() {
                 String::new()
+            } else {
+                format!(" ({})", cost_details.join(", "))
             };
             match self.debug_level {
                 0 => {}
                 1 => println!(
-                    "- Accessed feed pair {:?} for cache cost {}{}",
-                    feed_pair, pair_cost, new_entries_str
+                    "- Accessed {} {:?} for cache cost {}{}",
+                    step_name, feed_pair, pair_cost, new_entries_str
                 ),
                 _ => println!(
-                    "  * Total cache cost of this pair is {}{}",
-                    pair_cost, new_entries_str
+                    "  * Total cache cost of this {} is {}{}",
+                    step_kind, pair_cost, new_entries_str
                 ),
             }
-            total_cost += pair_cost;
-            new_entries_cost += pair_entries_cost;
+            total_cost.add_assign_checked(pair_cost)?;
+            write_cost.add_assign_checked(pair_write_cost)?;
+            new_entries_cost.add_assign_checked(pair_entries_cost)?;
             cumulative_cost.push(total_cost);
-            feed_load_count += 2;
         }
+        Ok(PathCosts {
+            total: total_cost,
+            new_entries: new_entries_cost,
+            write: write_cost,
+            cumulative: cumulative_cost,
+            stats: cache_sim
+                .stats()
+                .expect("Statistics were enabled above")
+                .clone(),
+        })
+    }
+
+    /// Report that the cost of an iterator could not be evaluated because it
+    /// overflowed the cost accumulator
+    fn report_overflow(&self, name: &str, error: CostOverflow) {
+        let error = error.to_string();
         match self.debug_level {
-            0 => {
-                println!(
-                    "│ {:<40} │ {:<16} │ {:<18} │ {:<13.2} │",
-                    name,
-                    total_cost,
-                    total_cost - new_entries_cost,
-                    (total_cost - new_entries_cost).to_num::<f32>() / (feed_load_count as f32)
-                )
-            }
-            _ => println!(
-                "- Total cache cost of this iterator is {}, {} w/o first accesses, {:.2} per feed load",
-                total_cost,
-                total_cost - new_entries_cost,
-                (total_cost - new_entries_cost).to_num::<f32>() / (feed_load_count as f32)
-            ),
+            0 if self.multi_core_model.is_some() => println!("│ {:<40} │ {:<111} │", name, error),
+            0 => println!("│ {:<40} │ {:<81} │", name, error),
+            _ => println!("- Could not evaluate this iterator: {}", error),
         }
-        let best_cost = self
-            .best_iterator
-            .as_ref()
-            .map(|(_name, cumulative_cost)| *cumulative_cost.last().unwrap())
-            .unwrap_or(cache::Cost::MAX);
-        if total_cost < best_cost {
-            self.best_iterator = Some((name.to_owned(), cumulative_cost.into()));
+    }
+
+    /// Test the locality of a path, as in `test_locality()`, when it is split into consecutive
+    /// chunks that are processed in parallel, and return the cost of each
+    /// thread along with their sum
+    ///
+    /// Threads are assumed to process feed pairs at the same rate.
+    ///
+    fn test_multi_core_locality<S: AsRef<[FeedIdx]>>(
+        &self,
+        multi_core_model: &MultiCoreModel,
+        feed_pairs: &[S],
+        output_accesses: bool,
+    ) -> Result<(Box<[C]>, C), CostOverflow> {
+        let num_threads = multi_core_model.num_threads();
+        let chunks = (0..num_threads)
+            .map(|thread| {
+                let start = thread * feed_pairs.len() / num_threads;
+                let end = (thread + 1) * feed_pairs.len() / num_threads;
+                &feed_pairs[start..end]
+            })
+            .collect::<Box<[_]>>();
+        let mut cache_sim = multi_core_model.start_simulation();
+        cache_sim.enable_recency_index(multi_core_model);
+        let mut thread_costs = vec![C::ZERO; num_threads].into_boxed_slice();
+        let max_chunk_len = chunks.iter().map(|chunk| chunk.len()).max().unwrap_or(0);
+        for pair_idx in 0..max_chunk_len {
+            for (thread, chunk) in chunks.iter().enumerate() {
+                let feed_pair = if let Some(feed_pair) = chunk.get(pair_idx) {
+                    feed_pair
+                } else {
+                    continue;
+                };
+                for &feed in feed_pair.as_ref().iter() {
+                    let feed_cost = cache_sim.simulate_access(multi_core_model, thread, feed)?;
+                    thread_costs[thread].add_assign_checked(accumulated_cost(feed_cost)?)?;
+                }
+                if output_accesses {
+                    let output_cost = cache_sim.simulate_output_access(multi_core_model, thread)?;
+                    thread_costs[thread].add_assign_checked(accumulated_cost(output_cost)?)?;
+                }
+            }
         }
+        let total_cost = C::checked_sum(thread_costs.iter().copied())?;
+        Ok((thread_costs, total_cost))
     }
 
     /// Tell which of the iterators that were tested so far got the best results
     ///
     /// If a tie occurs, pick the first iterator, as we're testing designs from
-    /// the simplest to the most complex ones.
+    /// the simplest to the most complex ones. If no iterator could be
+    /// evaluated, return None.
     ///
-    pub fn announce_best_iterator(&self) -> &[cache::Cost] {
+    pub fn announce_best_iterator(&self) -> Option<&[C]> {
         if self.debug_level > 0 {
             println!();
+        } else if self.multi_core_model.is_some() {
+            println!("└──────────────────────────────────────────┴──────────────────┴──────────────┴────────────────────┴────────────┴───────────────┴─────────────────┴───────────┘");
         } else {
-            println!("└──────────────────────────────────────────┴──────────────────┴────────────────────┴───────────────┘");
+            println!("└──────────────────────────────────────────┴──────────────────┴──────────────┴────────────────────┴────────────┴───────────────┘");
         }
-        let (best_name, cumulative_cost) = self
-            .best_iterator
-            .as_ref()
-            .expect("No iterator has been tested yet");
+        let (best_name, cumulative_cost, stats) = if let Some(best_iterator) = &self.best_iterator {
+            best_iterator
+        } else {
+            println!("No iterator could be evaluated");
+            return None;
+        };
+        let num_steps = cumulative_cost.len();
+        if num_steps <= MAX_PRINTED_STEPS {
+            println!(
+                "The best iterator so far is \"{}\" with cumulative cost at each step {:?}",
+                best_name, cumulative_cost
+            );
+        } else {
+            let checkpoints = (1..=10)
+                .map(|tenth| cumulative_cost[tenth * num_steps / 10 - 1])
+                .collect::<Vec<_>>();
+            println!(
+                "The best iterator so far is \"{}\" with cumulative cost after each tenth of its {} steps {:?}",
+                best_name, num_steps, checkpoints
+            );
+        }
+        println!(
+            "Its total cache cost is {:.1} L1 miss latencies",
+            self.latencies(*cumulative_cost.last().unwrap())
+        );
+        println!("Its cache access statistics are: {}", stats);
+        Some(&cumulative_cost[..])
+    }
+
+    /// Convert a cache cost into L1 miss latencies
+    ///
+    /// Cache costs are normalized by the cost of an L1 miss, which depends on
+    /// the size of feed entries, so they cannot be compared across entry
+    /// sizes. Latencies can.
+    ///
+    fn latencies(&self, cost: C) -> f32 {
+        cost.to_f32() * self.cache_model.cost_unit()
+    }
+}
+
+/// Report the stack distance histogram of every iterator, and the number of
+/// misses that a fully associative LRU cache would incur for every capacity
+///
+/// Unlike cache costs, these do not depend on the cache model, so they only
+/// need to be reported once per feed count.
+///
+pub fn announce_stack_distances(stack_distances: &[(String, StackDistanceHistogram)]) {
+    println!("Stack distance histograms, in feed loads per reuse distance:");
+    for (name, histogram) in stack_distances.iter() {
         println!(
-            "The best iterator so far is \"{}\" with cumulative cost at each step {:?}",
-            best_name, cumulative_cost
+            "- {}: {:?}, with {} cold accesses",
+            name,
+            histogram.counts(),
+            histogram.cold_accesses()
         );
-        &cumulative_cost[..]
+        let misses = (1..=histogram.counts().len().max(1))
+            .map(|capacity| histogram.misses(capacity))
+            .collect::<Vec<_>>();
+        println!(
+            "  * LRU misses for a capacity of 1 to {} feeds: {:?}",
+            misses.len(),
+            misses
+        );
+    }
+}
+
+/// Cache costs of a feed pair path
+struct PathCosts<C: CostAccumulator> {
+    /// Total cache cost
+    total: C,
+
+    /// Part of the total cost that is due to first accesses to cache entries
+    new_entries: C,
+
+    /// Part of the total cost that is due to writes
+    write: C,
+
+    /// Total cache cost after each step of the path
+    cumulative: Vec<C>,
+
+    /// Cache access statistics
+    stats: CacheStats,
+}
+
+/// Total cost of an access, accumulated into a type which may have more range
+/// than `AccessCost::total()`
+fn accumulated_cost<C: CostAccumulator>(cost: AccessCost) -> Result<C, CostOverflow> {
+    let mut total = C::from_cost(cost.read);
+    total.add_cost(cost.write)?;
+    Ok(total)
+}
+
+/// Ratio of the cost of the slowest thread to the average cost per thread,
+/// given the cost of each thread and their sum
+fn load_imbalance<C: CostAccumulator>(thread_costs: &[C], total_cost: C) -> f32 {
+    let max_cost = thread_costs
+        .iter()
+        .map(|cost| cost.to_f32())
+        .fold(0.0, f32::max);
+    let mean_cost = total_cost.to_f32() / thread_costs.len() as f32;
+    if mean_cost == 0.0 {
+        1.0
+    } else {
+        max_cost / mean_cost
     }
 }